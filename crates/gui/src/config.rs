@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::fs::{self, OpenOptions};
 use std::path::Path;
 
@@ -5,11 +6,58 @@ use serde::{Deserialize, Serialize};
 use std::fs::File;
 use std::io::prelude::*;
 use kv_log_macro::{error, info};
+use lcu::backup::RetentionPolicy;
+use lcu::ddragon::{self, GameDataProvider};
+use lcu::filters::BuildFilter;
+use lcu::item_sets::{ItemSetWriter, Ranking};
+use lcu::labels::{LabelRules, Labels};
+use lcu::pool::PoolMode;
+use lcu::updater::UpdateChannel;
 
-#[derive(Deserialize, Serialize, Default)]
+#[derive(Deserialize, Serialize, Default, Clone)]
+#[serde(default)]
 pub struct Config {
+    /// Selected sources, the preferred one first.
     pub selected_sources: Vec<String>,
     pub rune_source: String,
+    /// Serve sources, champions and runes from the local cache only.
+    pub offline_mode: bool,
+    /// Base urls of the build service, tried in order. Overridden by `CHAMPR_SERVICE_URL`.
+    pub service_urls: Vec<String>,
+    /// npm registries hosting `@champ-r/*`, tried in order. Overridden by `CHAMPR_NPM_REGISTRY`.
+    pub npm_registries: Vec<String>,
+    /// Where champions and rune trees come from, `service` or `data-dragon`.
+    pub game_data_provider: GameDataProvider,
+    /// Data Dragon locale such as `en_US` or `zh_CN`.
+    pub locale: String,
+    /// A folder of hand-written builds offered as an extra source, empty to disable.
+    pub local_builds_dir: String,
+    pub local_builds_label: String,
+    /// Don't ask GitHub for new releases on start.
+    pub skip_update_check: bool,
+    pub update_channel: UpdateChannel,
+    /// GitHub API url of the repository releases come from, e.g.
+    /// `https://api.github.com/repos/<owner>/<repo>`. Updates are not checked
+    /// while empty. Overridden by `CHAMPR_RELEASES_API`.
+    pub releases_api: String,
+    /// How many snapshots of `Config/Champions` are kept, and for how long.
+    pub backup_retention: RetentionPolicy,
+    /// Write one item set per champion and position holding every selected source.
+    pub merge_sources: bool,
+    /// Replacements for items removed from the game, old item id to new item id.
+    pub item_substitutions: HashMap<String, String>,
+    /// Positions such as `mid` whose item sets are listed first.
+    pub preferred_positions: Vec<String>,
+    /// Write item sets for the preferred positions only.
+    pub only_preferred_positions: bool,
+    /// Which champions get item sets.
+    pub pool_mode: PoolMode,
+    /// Champion aliases such as `MonkeyKing`, used with `PoolMode::Listed`.
+    pub champion_pool: Vec<String>,
+    /// Thresholds builds must meet to be written or shown.
+    pub build_filter: BuildFilter,
+    /// Title template and block renaming rules of written item sets.
+    pub item_set_labels: LabelRules,
 }
 
 const CONFIG_FILE_PATH: &str = ".settings.toml";
@@ -25,8 +73,9 @@ pub fn init_conf_file() -> Config {
 
 pub fn save_config(conf: &Config) {
     let mut conf_file = OpenOptions::new()
-        .read(true)
         .write(true)
+        .create(true)
+        .truncate(true)
         .open(CONFIG_FILE_PATH)
         .unwrap();
     let conf_str = toml::to_string(&conf).unwrap();
@@ -60,6 +109,25 @@ impl Config {
         Self {
             selected_sources: vec![],
             rune_source: String::from("op.gg"),
+            offline_mode: false,
+            service_urls: vec![],
+            npm_registries: vec![],
+            game_data_provider: GameDataProvider::Service,
+            locale: String::from(ddragon::DEFAULT_LOCALE),
+            local_builds_dir: String::new(),
+            local_builds_label: String::new(),
+            skip_update_check: false,
+            update_channel: UpdateChannel::Stable,
+            releases_api: String::new(),
+            backup_retention: RetentionPolicy::default(),
+            merge_sources: false,
+            item_substitutions: HashMap::new(),
+            preferred_positions: vec![],
+            only_preferred_positions: false,
+            pool_mode: PoolMode::All,
+            champion_pool: vec![],
+            build_filter: BuildFilter::default(),
+            item_set_labels: LabelRules::default(),
         }
     }
 
@@ -80,6 +148,85 @@ impl Config {
         self.save();
     }
 
+    pub fn set_selected_sources(&mut self, sources: &[String]) {
+        self.selected_sources = sources.to_vec();
+
+        self.save();
+    }
+
+    pub fn set_offline_mode(&mut self, offline: bool) {
+        self.offline_mode = offline;
+        lcu::cache::set_offline(offline);
+
+        self.save();
+    }
+
+    pub fn set_local_builds(&mut self, dir: &str, label: &str) {
+        self.local_builds_dir = dir.trim().to_string();
+        self.local_builds_label = label.trim().to_string();
+
+        self.save();
+    }
+
+    pub fn set_update_check(&mut self, skip: bool, channel: UpdateChannel) {
+        self.skip_update_check = skip;
+        self.update_channel = channel;
+
+        self.save();
+    }
+
+    pub fn set_releases_api(&mut self, url: &str) {
+        self.releases_api = url.trim().to_string();
+
+        self.save();
+    }
+
+    pub fn set_champion_pool(
+        &mut self,
+        mode: PoolMode,
+        champions: &[String],
+        positions: &[String],
+        only_positions: bool,
+    ) {
+        self.pool_mode = mode;
+        self.champion_pool = champions.to_vec();
+        self.preferred_positions = positions.to_vec();
+        self.only_preferred_positions = only_positions;
+
+        self.save();
+    }
+
+    pub fn set_build_filter(&mut self, filter: BuildFilter) {
+        self.build_filter = filter;
+
+        self.save();
+    }
+
+    pub fn set_item_set_labels(&mut self, rules: LabelRules) {
+        self.item_set_labels = rules;
+
+        self.save();
+    }
+
+    pub fn set_merge_sources(&mut self, merge: bool) {
+        self.merge_sources = merge;
+
+        self.save();
+    }
+
+    /// Writes item sets into the game at `lol_dir`, ranked by the selected
+    /// sources and preferred positions, labelled by `item_set_labels` in
+    /// `locale` and filtered by `build_filter`.
+    pub fn item_set_writer(&self, lol_dir: &str, is_tencent: bool) -> ItemSetWriter {
+        ItemSetWriter::for_game(lol_dir, is_tencent)
+            .with_ranking(Ranking::new(&self.selected_sources, &self.preferred_positions))
+            .with_labels(
+                Labels::new(self.item_set_labels.clone(), &self.locale)
+                    .with_sources(&self.selected_sources),
+            )
+            .with_filter(self.build_filter)
+    }
+
     pub fn save(&self) {
         save_config(self);
     }
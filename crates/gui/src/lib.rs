@@ -11,6 +11,16 @@ pub mod toggle_ui;
 pub mod ui;
 
 pub async fn run() -> Result<(), eframe::Error> {
+    let conf = config::read_and_init();
+    lcu::cache::set_offline(conf.offline_mode);
+    lcu::endpoints::configure(&conf.service_urls, &conf.npm_registries);
+    lcu::ddragon::configure(conf.game_data_provider, &conf.locale);
+    lcu::backup::configure(conf.backup_retention);
+    lcu::items::configure(&conf.item_substitutions);
+    tokio::spawn(lcu::endpoints::rank_all());
+    tokio::spawn(lcu::champions::load());
+    let conf = Arc::new(Mutex::new(conf));
+
     let lcu_auth = Arc::new(RwLock::new(CommandLineOutput::default()));
     let lcu_auth_ui = lcu_auth.clone();
     let lcu_auth_task = lcu_auth.clone();
@@ -50,6 +60,7 @@ pub async fn run() -> Result<(), eframe::Error> {
                 ui_cc_clone,
                 champion_id_ui,
                 random_mode_ui,
+                conf,
             );
             Box::new(app_data)
         }),
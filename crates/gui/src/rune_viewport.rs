@@ -2,6 +2,7 @@ use bytes::Bytes;
 use eframe::egui;
 use futures::future::join3;
 use image::EncodableLayout;
+use kv_log_macro as log;
 use poll_promise::Promise;
 use std::{
     collections::HashMap,
@@ -11,42 +12,50 @@ use std::{
 use lcu::{
     api::{self, Perk, RuneStyle, SummonerChampion},
     builds::{self, Rune},
+    champions,
     cmd::CommandLineOutput,
     lcu_error::LcuError,
+    local_source::{self, LOCAL_SOURCE},
+    patch::{Patch, Staleness},
+    provider,
     source::SourceItem,
-    web::{self, FetchError},
+    web::FetchError,
 };
 
+use crate::config::Config;
+
+type ChampionsAndPerksResult = (
+    Result<Vec<Perk>, LcuError>,
+    Result<Vec<SummonerChampion>, LcuError>,
+    Result<Vec<RuneStyle>, LcuError>,
+);
+
 #[derive(Default)]
 pub struct RuneUIState {
     pub sources: Vec<SourceItem>,
     #[cfg_attr(feature = "serde", serde(skip))]
-    pub fetch_sources_promise: Option<Promise<Result<Vec<SourceItem>, FetchError>>>,
-    pub all_champions: Vec<SummonerChampion>,
+    pub fetch_sources_promise: Option<Promise<anyhow::Result<Vec<SourceItem>>>>,
+    pub inventory_merged: bool,
     pub all_perks: Vec<Perk>,
     pub all_styles: Vec<RuneStyle>,
     #[cfg_attr(feature = "serde", serde(skip))]
-    pub fetch_champions_and_perks_promise: Option<
-        Promise<(
-            Result<Vec<Perk>, LcuError>,
-            Result<Vec<SummonerChampion>, LcuError>,
-            Result<Vec<RuneStyle>, LcuError>,
-        )>,
-    >,
+    pub fetch_champions_and_perks_promise: Option<Promise<ChampionsAndPerksResult>>,
     pub champion_id: Arc<Mutex<Option<i64>>>,
     pub champion_avatar_promise: Option<Promise<Result<Bytes, FetchError>>>,
     pub selected_source: String,
     pub builds: Vec<builds::BuildSection>,
     #[cfg_attr(feature = "serde", serde(skip))]
-    pub list_builds_by_alias_promise:
-        Option<Promise<Result<Vec<builds::BuildSection>, FetchError>>>,
+    pub list_builds_by_alias_promise: Option<Promise<anyhow::Result<Vec<builds::BuildSection>>>>,
     #[cfg_attr(feature = "serde", serde(skip))]
     pub apply_rune_promise: Option<Promise<Result<(), LcuError>>>,
     pub rune_to_apply: Option<Rune>,
     pub prev_champion_id: Option<i64>,
     #[cfg_attr(feature = "serde", serde(skip))]
-    pub apply_builds_from_current_source_promise: Option<Promise<Result<(), FetchError>>>,
+    pub apply_builds_from_current_source_promise: Option<Promise<anyhow::Result<()>>>,
     pub rune_images: HashMap<String, Bytes>,
+    pub local_generation: u64,
+    pub client_patch: Option<Patch>,
+    pub confirm_stale_apply: bool,
     #[cfg_attr(feature = "serde", serde(skip))]
     pub fetch_rune_promises: HashMap<String, Promise<Result<Bytes, FetchError>>>,
 }
@@ -56,6 +65,7 @@ pub fn render_runes_ui(
     ui_state: Arc<Mutex<RuneUIState>>,
     lcu_auth: Arc<RwLock<CommandLineOutput>>,
     champion_id: Arc<RwLock<Option<i64>>>,
+    config: Arc<Mutex<Config>>,
 ) {
     egui_extras::install_image_loaders(ctx);
 
@@ -78,6 +88,15 @@ pub fn render_runes_ui(
 
             let cid = champion_id.read().unwrap().unwrap_or_default();
 
+            let local_generation = local_source::generation();
+            if ui_state.local_generation != local_generation {
+                ui_state.local_generation = local_generation;
+                ui_state.fetch_sources_promise = None;
+                if ui_state.selected_source == LOCAL_SOURCE {
+                    ui_state.list_builds_by_alias_promise = None;
+                }
+            }
+
             match &ui_state.fetch_champions_and_perks_promise {
                 Some(p) => match p.ready() {
                     None => {
@@ -89,16 +108,22 @@ pub fn render_runes_ui(
                                 ui_state.all_perks = perks.clone();
                             }
                             Err(err) => {
-                                ui.label(format!("Failed to list perks: {:?}", err));
+                                ui.colored_label(
+                                    ui.visuals().error_fg_color,
+                                    format!("Failed to list perks: {}", err),
+                                );
                             }
                         };
                         match champions_result {
                             Ok(champions) => {
-                                ui_state.all_champions = champions.clone();
+                                if !ui_state.inventory_merged {
+                                    champions::update_from_inventory(champions);
+                                    ui_state.inventory_merged = true;
+                                }
 
                                 if cid > 0 {
-                                    let cur_champion = champions.iter().find(|c| c.id == cid);
-                                    if let Some(champ) = cur_champion {
+                                    let registry = champions::shared();
+                                    if let Some(champ) = registry.by_id(cid) {
                                         let champion_icon = format!(
                                             "https://game.gtimg.cn/images/lol/act/img/champion/{}.png",
                                             &champ.alias
@@ -112,7 +137,10 @@ pub fn render_runes_ui(
                                 }
                             }
                             Err(err) => {
-                                ui.label(format!("Failed to list owned champions: {:?}", err));
+                                ui.colored_label(
+                                    ui.visuals().error_fg_color,
+                                    format!("Failed to list owned champions: {}", err),
+                                );
                             }
                         };
                         match styles_result {
@@ -120,7 +148,10 @@ pub fn render_runes_ui(
                                 ui_state.all_styles = styles.clone();
                             }
                             Err(err) => {
-                                ui.label(format!("Failed to list styles: {:?}", err));
+                                ui.colored_label(
+                                    ui.visuals().error_fg_color,
+                                    format!("Failed to list styles: {}", err),
+                                );
                             }
                         };
                     }
@@ -170,17 +201,21 @@ pub fn render_runes_ui(
                                             ui_state.list_builds_by_alias_promise = None;
                                             ui_state.apply_builds_from_current_source_promise =
                                                 None;
+                                            ui_state.confirm_stale_apply = false;
                                         };
                                     });
                                 });
                         }
                         Some(Err(err)) => {
-                            ui.label(format!("Failed to fetch sources: {:?}", err));
+                            ui.colored_label(
+                                ui.visuals().error_fg_color,
+                                format!("Failed to fetch sources: {:#}", err),
+                            );
                         }
                     },
                     None => {
                         let promise =
-                            Promise::spawn_async(async move { web::fetch_sources().await });
+                            Promise::spawn_async(async move { provider::list_sources().await });
                         ui_state.fetch_sources_promise = Some(promise);
                     }
                 };
@@ -193,6 +228,7 @@ pub fn render_runes_ui(
                 ui_state.rune_to_apply = None;
                 ui_state.prev_champion_id = Some(cid);
                 ui_state.apply_builds_from_current_source_promise = None;
+                ui_state.confirm_stale_apply = false;
             }
             if !ui_state.selected_source.is_empty() && cid > 0 {
                 match &ui_state.list_builds_by_alias_promise {
@@ -203,6 +239,12 @@ pub fn render_runes_ui(
                         Some(Ok(builds)) => {
                             ui_state.builds = builds.clone();
 
+                            if let Some(describe) =
+                                builds_staleness(builds, ui_state.client_patch).describe()
+                            {
+                                ui.colored_label(ui.visuals().warn_fg_color, describe);
+                            }
+
                             builds.iter().for_each(|build| {
                                 build.runes.iter().for_each(|rune| {
                                     ui.label(&rune.name);
@@ -216,7 +258,7 @@ pub fn render_runes_ui(
                                             .iter()
                                             .find(|p| p.id == rune.sub_style_id);
 
-                                        let icon_paths = vec![
+                                        let icon_paths = [
                                             primary_perk.map(|p| p.icon_path.clone()),
                                             sub_perk.map(|p| p.icon_path.clone()),
                                         ];
@@ -288,18 +330,23 @@ pub fn render_runes_ui(
                             });
                         }
                         Some(Err(err)) => {
-                            ui.label(format!("Failed to fetch builds: {:?}", err));
+                            ui.colored_label(
+                                ui.visuals().error_fg_color,
+                                format!("Failed to fetch builds: {:#}", err),
+                            );
                         }
                     },
                     None => {
-                        let champion = &ui_state.all_champions.iter().find(|c| c.id == cid);
-                        if champion.is_some() {
-                            let c = (*champion).unwrap();
+                        if let Some(c) = champions::shared().by_id(cid) {
                             let source = ui_state.selected_source.clone();
                             let alias = &c.alias;
                             let champion_alias = alias.clone();
+                            let filter = config.lock().unwrap().build_filter;
                             let promise = Promise::spawn_async(async move {
-                                web::list_builds_by_alias(&source, &champion_alias).await
+                                let mut builds =
+                                    provider::list_builds(&source, &champion_alias).await?;
+                                filter.filter_runes(&mut builds);
+                                Ok(builds)
                             });
                             ui_state.list_builds_by_alias_promise = Some(promise);
                         }
@@ -320,7 +367,10 @@ pub fn render_runes_ui(
                                 ui_state.rune_to_apply = None;
                             }
                             Some(Err(err)) => {
-                                println!("apply rune failed: {:?}", err);
+                                ui.colored_label(
+                                    ui.visuals().error_fg_color,
+                                    format!("Failed to apply rune: {}", err),
+                                );
                             }
                         },
                         None => {
@@ -332,45 +382,78 @@ pub fn render_runes_ui(
                     }
                 }
 
+                let staleness = builds_staleness(&ui_state.builds, ui_state.client_patch);
                 ui.horizontal(|ui| {
-                    if ui
-                        .button(format!("Apply builds from {}", ui_state.selected_source))
-                        .clicked()
-                    {
-                        match &ui_state.apply_builds_from_current_source_promise {
-                            Some(p) => match p.ready() {
-                                None => {
-                                    ui.spinner();
-                                }
-                                Some(Ok(_)) => {
-                                    ui_state.apply_builds_from_current_source_promise = None;
-                                }
-                                Some(Err(err)) => {
-                                    println!("apply builds failed: {:?}", err);
-                                }
-                            },
+                    let label = if ui_state.confirm_stale_apply {
+                        String::from("Apply anyway")
+                    } else {
+                        format!("Apply builds from {}", ui_state.selected_source)
+                    };
+                    let applying = ui_state
+                        .apply_builds_from_current_source_promise
+                        .as_ref()
+                        .is_some_and(|p| p.ready().is_none());
+                    if ui.add_enabled(!applying, egui::Button::new(label)).clicked() {
+                        if staleness.needs_warning() && !ui_state.confirm_stale_apply {
+                            ui_state.confirm_stale_apply = true;
+                        } else {
+                            ui_state.confirm_stale_apply = false;
+                            let selected_source = ui_state.selected_source.clone();
+                            if let Some(target_champion) = champions::shared().by_id(cid) {
+                                let champion_name = target_champion.alias.clone();
+                                let writer =
+                                    config.lock().unwrap().item_set_writer(&dir, is_tencent);
+                                let p = Promise::spawn_async(async move {
+                                    builds::apply_builds_from_source(
+                                        &writer,
+                                        &selected_source,
+                                        &champion_name,
+                                    )
+                                    .await
+                                    .inspect_err(|err| log::error!("apply builds failed: {:#}", err))
+                                });
+                                ui_state.apply_builds_from_current_source_promise = Some(p);
+                            }
+                        }
+                    }
+                    if let Some(p) = &ui_state.apply_builds_from_current_source_promise {
+                        match p.ready() {
                             None => {
-                                let selected_source = ui_state.selected_source.clone();
-                                let target_champion =
-                                    ui_state.all_champions.iter().find(|c| c.id == cid);
-                                if target_champion.is_some() {
-                                    let champion_name = target_champion.unwrap().alias.clone();
-                                    let p = Promise::spawn_async(async move {
-                                        builds::apply_builds_from_source(
-                                            &dir,
-                                            &selected_source,
-                                            &champion_name,
-                                            is_tencent,
-                                        )
-                                            .await
-                                    });
-                                    ui_state.apply_builds_from_current_source_promise = Some(p);
-                                }
+                                ui.spinner();
+                            }
+                            Some(Ok(_)) => {
+                                ui_state.apply_builds_from_current_source_promise = None;
+                            }
+                            Some(Err(err)) => {
+                                ui.colored_label(
+                                    ui.visuals().error_fg_color,
+                                    format!("Failed to apply builds: {:#}", err),
+                                );
                             }
                         }
                     }
+                    if ui_state.confirm_stale_apply {
+                        ui.colored_label(
+                            ui.visuals().warn_fg_color,
+                            "These builds are more than one patch old",
+                        );
+                    }
                 });
             }
         }
     });
 }
+
+/// Compares the patch the builds were made for with the running client.
+fn builds_staleness(builds: &[builds::BuildSection], client: Option<Patch>) -> Staleness {
+    builds
+        .first()
+        .map(|b| {
+            if b.official_version.is_empty() {
+                &b.version
+            } else {
+                &b.official_version
+            }
+        })
+        .map_or(Staleness::Unknown, |version| Staleness::of(version, client))
+}
@@ -1,5 +1,7 @@
 use futures::future::join_all;
 
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::{
     atomic::{AtomicBool, Ordering},
     Arc, Mutex, RwLock,
@@ -11,24 +13,80 @@ use kv_log_macro as log;
 use poll_promise::Promise;
 
 use lcu::{
+    api,
+    backup::{self, Snapshot},
+    cache,
+    cancel::{self, CancellationToken},
+    champions,
+    changelog::{self, BuildHistory, ChangelogEntry, HISTORY_FILE},
     cmd::CommandLineOutput,
+    endpoints,
+    filters::BuildFilter,
+    item_sets::{ItemSetWriter, Plan, WriteReport},
+    items,
+    labels::LabelRules,
+    lcu_error::LcuError,
+    local_source::{self, LocalFolderProvider, LOCAL_SOURCE},
+    merge::MERGED_SOURCE,
+    packages::{self, PackageStatus},
+    patch::{Patch, Staleness},
+    pool::{self, PoolMode},
+    progress::{self, ApplyStage, ProgressReceiver, ProgressSender},
+    provider,
     source::SourceItem,
-    web::{self},
+    updater::{self, UpdateChannel},
+    web::{self, FetchError, LatestRelease},
 };
 
 use crate::{
+    config::Config,
     rune_viewport::{render_runes_ui, RuneUIState},
     toggle_ui,
 };
 
+/// Where the changelog is exported to, next to `.settings.toml`.
+const CHANGELOG_EXPORT: &str = "build-changelog.md";
+
+type SourcePlans = Vec<(String, anyhow::Result<Plan>)>;
+
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 #[derive(Default)]
 pub struct SourceWindow {
     pub sources: Vec<SourceItem>,
     #[cfg_attr(feature = "serde", serde(skip))]
-    pub sources_promise: Option<Promise<Result<Vec<SourceItem>, web::FetchError>>>,
+    pub sources_promise: Option<Promise<anyhow::Result<Vec<SourceItem>>>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub plan_promise: Option<Promise<SourcePlans>>,
+    pub pending_plans: Vec<(String, Plan)>,
+    pub apply_target: Option<(String, bool)>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub apply_builds_promise: Option<Promise<Vec<anyhow::Result<WriteReport>>>>,
+    pub apply_builds_errors: Vec<String>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub apply_progress_rx: Option<ProgressReceiver>,
+    pub apply_progress: Vec<(String, ApplyStage)>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub apply_cancel: Option<CancellationToken>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub package_status_promise: Option<Promise<HashMap<String, (PackageStatus, String)>>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub game_version_promise: Option<(String, Promise<Result<String, LcuError>>)>,
+    pub client_patch: Option<Patch>,
+    pub stale_apply_warning: Vec<String>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub update_promise: Option<Promise<Result<Option<LatestRelease>, FetchError>>>,
+    pub update_dismissed: bool,
+    pub releases_api: String,
     #[cfg_attr(feature = "serde", serde(skip))]
-    pub apply_builds_promise: Option<Promise<Vec<Result<(), anyhow::Error>>>>,
+    pub self_update_promise: Option<Promise<anyhow::Result<PathBuf>>>,
+    pub backups: Vec<Snapshot>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub restore_promise: Option<Promise<std::io::Result<()>>>,
+    pub restore_message: Option<Result<String, String>>,
+    /// Newest first.
+    pub changelog: Vec<ChangelogEntry>,
+    pub changelog_filter: String,
+    pub changelog_message: Option<Result<String, String>>,
 
     pub selected_sources: Vec<String>,
     pub lcu_auth: Arc<RwLock<CommandLineOutput>>,
@@ -36,6 +94,20 @@ pub struct SourceWindow {
 
     pub random_mode: Arc<Mutex<bool>>,
     pub champion_id: Arc<RwLock<Option<i64>>>,
+    pub config: Arc<Mutex<Config>>,
+    pub local_builds_dir: String,
+    pub local_builds_label: String,
+    pub build_filter: BuildFilter,
+    pub title_template: String,
+    /// One `From = To` rule per line.
+    pub block_renames_text: String,
+    pub pool_mode: PoolMode,
+    pub champion_pool_text: String,
+    pub unknown_pool_champions: Vec<String>,
+    pub preferred_positions: Vec<String>,
+    pub only_preferred_positions: bool,
+    pub local_generation: u64,
+    pub local_watch_handle: Option<AbortHandle>,
 
     pub rune_viewport_ctx: Arc<Mutex<Option<egui::Context>>>,
     // rune viewport
@@ -50,16 +122,753 @@ impl SourceWindow {
         rune_viewport_ctx: Arc<Mutex<Option<egui::Context>>>,
         champion_id: Arc<RwLock<Option<i64>>>,
         random_mode: Arc<Mutex<bool>>,
+        config: Arc<Mutex<Config>>,
     ) -> Self {
-        Self {
+        let conf = config.lock().unwrap().clone();
+        let mut window = Self {
             lcu_auth,
             lcu_task_handle,
             rune_viewport_ctx,
             champion_id,
             random_mode,
+            config,
+            local_builds_dir: conf.local_builds_dir,
+            local_builds_label: conf.local_builds_label,
+            selected_sources: conf.selected_sources,
+            build_filter: conf.build_filter,
+            releases_api: conf.releases_api.clone(),
+            title_template: conf.item_set_labels.title_template.clone(),
+            block_renames_text: conf
+                .item_set_labels
+                .block_renames
+                .iter()
+                .map(|(from, to)| format!("{from} = {to}"))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            pool_mode: conf.pool_mode,
+            champion_pool_text: conf.champion_pool.join(", "),
+            preferred_positions: conf.preferred_positions,
+            only_preferred_positions: conf.only_preferred_positions,
             ..Default::default()
+        };
+        window.setup_local_source();
+        window.check_for_update();
+        window
+    }
+
+    /// Looks for a newer release in the background, unless turned off.
+    fn check_for_update(&mut self) {
+        let (skip, channel, api) = {
+            let conf = self.config.lock().unwrap();
+            (
+                conf.skip_update_check,
+                conf.update_channel,
+                updater::releases_api(&conf.releases_api),
+            )
+        };
+        self.update_dismissed = false;
+        let Some(api) = api.filter(|_| !skip) else {
+            self.update_promise = None;
+            return;
+        };
+        self.update_promise = Some(Promise::spawn_async(async move {
+            updater::check_for_update(&api, channel)
+                .await
+                .inspect_err(|err| log::warn!("check for update: {}", err))
+        }));
+    }
+
+    fn render_update_banner(&mut self, ctx: &egui::Context) {
+        if self.update_dismissed {
+            return;
+        }
+        let Some(Ok(Some(release))) = self.update_promise.as_ref().and_then(|p| p.ready()) else {
+            return;
+        };
+        let release = release.clone();
+
+        egui::TopBottomPanel::top("update_banner").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(format!(
+                    "ChampR {} is available, you are on {}.",
+                    release.tag_name,
+                    lcu::constants::VERSION
+                ));
+                ui.hyperlink_to("Release notes", &release.html_url);
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    if ui.small_button("✕").on_hover_text("Dismiss").clicked() {
+                        self.update_dismissed = true;
+                    }
+                    self.render_self_update(ui, &release);
+                });
+            });
+        });
+    }
+
+    fn render_self_update(&mut self, ui: &mut egui::Ui, release: &LatestRelease) {
+        match self.self_update_promise.as_ref().map(|p| p.ready()) {
+            None => {
+                let asset =
+                    updater::platform_asset(release, std::env::consts::OS, std::env::consts::ARCH);
+                if asset.is_some() && ui.button("Update now").clicked() {
+                    let release = release.clone();
+                    self.self_update_promise = Some(Promise::spawn_async(async move {
+                        let exe = std::env::current_exe()?;
+                        let dir = std::env::temp_dir().join("champr-update");
+                        let binary =
+                            updater::download_update(&release, &dir, &Default::default()).await?;
+                        let backup = updater::install_update(&binary, &exe)?;
+                        let _ = std::fs::remove_dir_all(&dir);
+                        Ok(backup)
+                    }));
+                }
+            }
+            Some(None) => {
+                ui.spinner();
+                ui.label("Downloading…");
+            }
+            Some(Some(Ok(_))) => {
+                if ui.button("Restart now").clicked() {
+                    match std::env::current_exe().and_then(|exe| updater::relaunch(&exe)) {
+                        Ok(_) => ui.ctx().send_viewport_cmd(egui::ViewportCommand::Close),
+                        Err(err) => log::error!("restart after update: {}", err),
+                    }
+                }
+                if ui.small_button("Roll back").clicked() {
+                    match std::env::current_exe().and_then(|exe| updater::rollback(&exe)) {
+                        Ok(_) => self.self_update_promise = None,
+                        Err(err) => log::error!("roll back update: {}", err),
+                    }
+                }
+            }
+            Some(Some(Err(err))) => {
+                ui.colored_label(
+                    ui.visuals().error_fg_color,
+                    format!("Update failed: {:#}", err),
+                );
+                if ui.small_button("Retry").clicked() {
+                    self.self_update_promise = None;
+                }
+            }
+        }
+    }
+
+    /// Registers the local builds folder as a source and watches it for changes.
+    fn setup_local_source(&mut self) {
+        if let Some(handle) = self.local_watch_handle.take() {
+            handle.abort();
+        }
+        provider::unregister(LOCAL_SOURCE);
+
+        let dir = self.local_builds_dir.trim().to_string();
+        if dir.is_empty() {
+            return;
+        }
+        provider::register(Arc::new(LocalFolderProvider::new(
+            &self.local_builds_label,
+            &dir,
+        )));
+        let ui_ctx = self.rune_viewport_ctx.clone();
+        let handle = tokio::spawn(local_source::watch(dir.into(), ui_ctx));
+        self.local_watch_handle = Some(handle.abort_handle());
+    }
+
+    /// Keeps `client_patch` in sync with the game the LCU is connected to.
+    fn update_client_patch(&mut self) {
+        let auth_url = self.lcu_auth.read().unwrap().auth_url.clone();
+        if auth_url.is_empty() {
+            self.game_version_promise = None;
+            self.client_patch = None;
+        } else if self
+            .game_version_promise
+            .as_ref()
+            .is_none_or(|(url, _)| url != &auth_url)
+        {
+            let endpoint = format!("https://{}", &auth_url);
+            tokio::spawn(items::load_from_lcu(endpoint.clone()));
+            let promise =
+                Promise::spawn_async(async move { api::get_game_version(&endpoint).await });
+            self.game_version_promise = Some((auth_url, promise));
+        } else if let Some((_, p)) = &self.game_version_promise {
+            match p.ready() {
+                Some(Ok(version)) => self.client_patch = Patch::parse(version),
+                Some(Err(err)) => {
+                    log::warn!("read game version: {}", err);
+                    self.client_patch = None;
+                }
+                None => {}
+            }
+        }
+
+        self.rune_ui_state.lock().unwrap().client_patch = self.client_patch;
+    }
+
+    fn staleness(&self, source: &str) -> Staleness {
+        self.package_status_promise
+            .as_ref()
+            .and_then(|p| p.ready())
+            .and_then(|statuses| statuses.get(source))
+            .map_or(Staleness::Unknown, |(_, data_version)| {
+                Staleness::of(data_version, self.client_patch)
+            })
+    }
+
+    fn start_progress(&mut self, sources: &[String], stage: ApplyStage) -> ProgressSender {
+        let (tx, rx) = progress::channel();
+        self.apply_progress_rx = Some(rx);
+        self.apply_progress = sources
+            .iter()
+            .map(|source| (source.clone(), stage.clone()))
+            .collect();
+        tx
+    }
+
+    fn save_selected_sources(&self) {
+        self.config
+            .lock()
+            .unwrap()
+            .set_selected_sources(&self.selected_sources);
+    }
+
+    /// Lists the selected sources with controls to reorder them. The first one
+    /// is preferred when merging and its item sets are listed first in the shop.
+    fn render_source_priority(&mut self, ui: &mut egui::Ui) {
+        let count = self.selected_sources.len();
+        let mut swap = None;
+        for (idx, source) in self.selected_sources.iter().enumerate() {
+            let label = self
+                .sources
+                .iter()
+                .find(|s| &s.value == source)
+                .map_or(source.as_str(), |s| s.label.as_str());
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(idx > 0, egui::Button::new("⬆").small())
+                    .on_hover_text("Prefer this source")
+                    .clicked()
+                {
+                    swap = Some((idx - 1, idx));
+                }
+                if ui
+                    .add_enabled(idx + 1 < count, egui::Button::new("⬇").small())
+                    .clicked()
+                {
+                    swap = Some((idx, idx + 1));
+                }
+                ui.label(format!("{}. {label}", idx + 1));
+            });
+        }
+        if let Some((a, b)) = swap {
+            self.selected_sources.swap(a, b);
+            self.save_selected_sources();
+        }
+    }
+
+    /// The title template and block renaming rules of written item sets.
+    fn render_item_set_labels(&mut self, ui: &mut egui::Ui) {
+        ui.label("Title");
+        ui.add(
+            egui::TextEdit::singleline(&mut self.title_template)
+                .hint_text("{source} {position} {win_rate}% ({pick_count})"),
+        )
+        .on_hover_text(
+            "Also {champion}, {alias}, {version} and {title}. Empty keeps the source's title",
+        );
+        ui.label("Rename blocks")
+            .on_hover_text("One rule per line, e.g. Starters = Start");
+        ui.add(
+            egui::TextEdit::multiline(&mut self.block_renames_text)
+                .desired_rows(3)
+                .hint_text("Starters = Start"),
+        );
+        if ui.button("Save").clicked() {
+            let block_renames = self
+                .block_renames_text
+                .lines()
+                .filter_map(|line| line.split_once('='))
+                .map(|(from, to)| (from.trim().to_string(), to.trim().to_string()))
+                .filter(|(from, _)| !from.is_empty())
+                .collect();
+            self.config.lock().unwrap().set_item_set_labels(LabelRules {
+                title_template: self.title_template.trim().to_string(),
+                block_renames,
+            });
+        }
+    }
+
+    /// Which champions and positions get item sets, and which positions come first.
+    fn render_champion_pool(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut self.pool_mode, PoolMode::All, "All champions");
+            ui.radio_value(&mut self.pool_mode, PoolMode::Listed, "My pool");
+            ui.radio_value(&mut self.pool_mode, PoolMode::Owned, "Owned")
+                .on_hover_text("Champions you own or rent, read from the client");
+        });
+        if self.pool_mode == PoolMode::Listed {
+            ui.add(
+                egui::TextEdit::singleline(&mut self.champion_pool_text)
+                    .hint_text("Ahri, Wukong, Lee Sin"),
+            );
+        }
+        if !self.unknown_pool_champions.is_empty() {
+            ui.colored_label(
+                ui.visuals().warn_fg_color,
+                format!(
+                    "Unknown champions: {}",
+                    self.unknown_pool_champions.join(", ")
+                ),
+            );
+        }
+
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Positions");
+            for lane in pool::LANES {
+                let mut checked = self.preferred_positions.iter().any(|p| p == lane);
+                if ui.checkbox(&mut checked, *lane).changed() {
+                    self.preferred_positions.retain(|p| p != lane);
+                    if checked {
+                        self.preferred_positions.push(lane.to_string());
+                    }
+                }
+            }
+        });
+        ui.checkbox(
+            &mut self.only_preferred_positions,
+            "Only write these positions",
+        )
+        .on_hover_text("Otherwise they are just listed first");
+
+        if ui.button("Save").clicked() {
+            let registry = champions::shared();
+            let (mut listed, mut unknown) = (vec![], vec![]);
+            for name in self.champion_pool_text.split(',').map(str::trim) {
+                match registry.by_alias(name).or_else(|| registry.by_name(name)) {
+                    Some(champion) => listed.push(champion.alias.clone()),
+                    // kept as typed until the champion list has loaded
+                    None if registry.is_empty() => listed.push(name.to_string()),
+                    None if !name.is_empty() => unknown.push(name.to_string()),
+                    None => {}
+                }
+            }
+            self.unknown_pool_champions = unknown;
+            self.config.lock().unwrap().set_champion_pool(
+                self.pool_mode,
+                &listed,
+                &self.preferred_positions,
+                self.only_preferred_positions,
+            );
+        }
+    }
+
+    /// Works out what applying would change, the preview is shown once it is ready.
+    fn start_apply(&mut self, lol_dir: String, is_tencent: bool) {
+        log::info!("start planning builds");
+        self.apply_builds_errors.clear();
+
+        let selected_sources = self.selected_sources.clone();
+        let (merge, pool_mode, listed, positions, writer) = {
+            let conf = self.config.lock().unwrap();
+            let positions = if conf.only_preferred_positions {
+                conf.preferred_positions.clone()
+            } else {
+                vec![]
+            };
+            (
+                conf.merge_sources,
+                conf.pool_mode,
+                conf.champion_pool.clone(),
+                positions,
+                conf.item_set_writer(&lol_dir, is_tencent),
+            )
+        };
+        let endpoint = format!("https://{}", self.lcu_auth.read().unwrap().auth_url);
+        let tx = self.start_progress(&selected_sources, ApplyStage::Resolving);
+        let token = CancellationToken::new();
+        self.apply_cancel = Some(token.clone());
+        self.apply_target = Some((lol_dir.clone(), is_tencent));
+
+        let promise = Promise::spawn_async(async move {
+            let pool = match pool::resolve(pool_mode, &listed, &positions, &endpoint).await {
+                Ok(pool) => pool,
+                Err(err) => {
+                    let err = anyhow::Error::from(err).context("Failed to list owned champions");
+                    return vec![(String::from("Champion pool"), Err(err))];
+                }
+            };
+            if merge {
+                let plan =
+                    web::plan_merged(&selected_sources, &writer, &pool, Some(tx), token).await;
+                return vec![(MERGED_SOURCE.to_string(), plan)];
+            }
+
+            let tasks = selected_sources.iter().map(|source| async {
+                let plan =
+                    web::plan_for_source(source, &writer, &pool, Some(tx.clone()), token.clone())
+                        .await;
+                (source.clone(), plan)
+            });
+            let mut plans = join_all(tasks).await;
+            // merged item sets from an earlier apply are replaced by the separate ones
+            match writer.plan_source_removal(MERGED_SOURCE) {
+                Ok(plan) if plan.is_empty() => {}
+                result => plans.push((MERGED_SOURCE.to_string(), result.map_err(Into::into))),
+            }
+            plans
+        });
+        self.plan_promise = Some(promise);
+    }
+
+    fn plan_removal(&mut self, lol_dir: String, is_tencent: bool) {
+        self.apply_builds_errors.clear();
+        match ItemSetWriter::for_game(&lol_dir, is_tencent).plan_removal() {
+            Ok(plan) => {
+                self.apply_target = Some((lol_dir, is_tencent));
+                self.pending_plans = vec![(String::from("ChampR builds"), plan)];
+            }
+            Err(err) => self.apply_builds_errors.push(format!(
+                "Failed to read the item sets ChampR wrote: {}",
+                err
+            )),
+        }
+    }
+
+    fn refresh_changelog(&mut self) {
+        match BuildHistory::load(Path::new(HISTORY_FILE)) {
+            Ok(history) => self.changelog = history.newest_first().cloned().collect(),
+            Err(err) => self.changelog_message = Some(Err(format!("{:#}", err))),
+        }
+    }
+
+    /// What changed in the builds between applies, filtered by champion or source.
+    fn render_changelog(&mut self, ui: &mut egui::Ui) {
+        match &self.changelog_message {
+            Some(Ok(message)) => {
+                ui.label(message);
+            }
+            Some(Err(err)) => {
+                ui.colored_label(ui.visuals().error_fg_color, err);
+            }
+            None => {}
+        }
+
+        let filter = self.changelog_filter.trim().to_lowercase();
+        let entries = self
+            .changelog
+            .iter()
+            .filter(|e| {
+                filter.is_empty()
+                    || e.champion.to_lowercase().contains(&filter)
+                    || e.source.to_lowercase().contains(&filter)
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let (mut refresh, mut export) = (false, false);
+        ui.horizontal(|ui| {
+            refresh = ui.button("Refresh").clicked();
+            ui.add(
+                egui::TextEdit::singleline(&mut self.changelog_filter)
+                    .hint_text("Champion or source")
+                    .desired_width(140.),
+            );
+            export = ui
+                .add_enabled(!entries.is_empty(), egui::Button::new("Export Markdown"))
+                .on_hover_text(format!("Write the listed changes to {CHANGELOG_EXPORT}"))
+                .clicked();
+        });
+        if refresh {
+            self.changelog_message = None;
+            self.refresh_changelog();
+        }
+        if export {
+            let markdown = changelog::to_markdown(&entries, &items::shared());
+            self.changelog_message = Some(match std::fs::write(CHANGELOG_EXPORT, markdown) {
+                Ok(_) => Ok(format!("Exported to {CHANGELOG_EXPORT}")),
+                Err(err) => Err(format!("Failed to export: {:#}", err)),
+            });
+        }
+
+        if self.changelog.is_empty() {
+            ui.label("Nothing yet, builds are compared from their second apply on.");
+            return;
+        }
+        let catalog = items::shared();
+        egui::ScrollArea::vertical()
+            .id_source("changelog")
+            .max_height(240.)
+            .show(ui, |ui| {
+                for entry in entries.iter() {
+                    let versions = if entry.from_version == entry.to_version {
+                        entry.to_version.clone()
+                    } else {
+                        format!("{} → {}", entry.from_version, entry.to_version)
+                    };
+                    ui.label(
+                        egui::RichText::new(format!(
+                            "{} {} ({}, {})",
+                            entry.champion, entry.position, entry.source, versions
+                        ))
+                        .strong(),
+                    )
+                    .on_hover_text(format!("Applied {} UTC", entry.applied_label()));
+                    for change in entry.changes.iter() {
+                        ui.label(format!("  • {}", change.describe(&catalog)));
+                    }
+                }
+            });
+    }
+
+    fn refresh_backups(&mut self, lol_dir: &str, is_tencent: bool) {
+        let writer = ItemSetWriter::for_game(lol_dir, is_tencent);
+        match backup::list(writer.backup_dir()) {
+            Ok(backups) => self.backups = backups,
+            Err(err) => self.restore_message = Some(Err(format!("{:#}", err))),
+        }
+    }
+
+    fn render_backups(&mut self, ui: &mut egui::Ui, lol_dir: &str, is_tencent: bool) {
+        if let Some(promise) = &self.restore_promise {
+            match promise.ready() {
+                None => {
+                    ui.horizontal(|ui| {
+                        ui.spinner();
+                        ui.label("Restoring...");
+                    });
+                    return;
+                }
+                Some(result) => {
+                    self.restore_message = Some(match result {
+                        Ok(_) => Ok(String::from("Restored, reopen the item sets in the client")),
+                        Err(err) => Err(format!("Failed to restore: {:#}", err)),
+                    });
+                    self.restore_promise = None;
+                    self.refresh_backups(lol_dir, is_tencent);
+                }
+            }
+        }
+
+        match &self.restore_message {
+            Some(Ok(message)) => {
+                ui.label(message);
+            }
+            Some(Err(err)) => {
+                ui.colored_label(ui.visuals().error_fg_color, err);
+            }
+            None => {}
+        }
+        if ui.button("Refresh").clicked() {
+            self.restore_message = None;
+            self.refresh_backups(lol_dir, is_tencent);
+        }
+        if self.backups.is_empty() {
+            ui.label("No snapshots yet, one is taken before every write.");
+            return;
+        }
+
+        let mut selected = None;
+        for snapshot in self.backups.iter() {
+            ui.horizontal(|ui| {
+                ui.label(format!(
+                    "{} UTC ({} KB)",
+                    snapshot.created_label(),
+                    snapshot.size.div_ceil(1024)
+                ));
+                if ui
+                    .button("Restore")
+                    .on_hover_text("Replace the item sets with this snapshot")
+                    .clicked()
+                {
+                    selected = Some(snapshot.clone());
+                }
+            });
+        }
+        if let Some(snapshot) = selected {
+            log::info!("restore item sets from {:?}", &snapshot.path);
+            let writer = ItemSetWriter::for_game(lol_dir, is_tencent);
+            let (dir, backup_dir) = (
+                writer.dir().to_path_buf(),
+                writer.backup_dir().to_path_buf(),
+            );
+            self.restore_message = None;
+            self.restore_promise = Some(Promise::spawn_async(async move {
+                tokio::task::spawn_blocking(move || {
+                    let _lock = lcu::manifest::lock();
+                    backup::restore(&snapshot, &dir, &backup_dir)
+                })
+                .await
+                .unwrap_or_else(|err| Err(std::io::Error::other(err)))
+            }));
         }
     }
+
+    fn write_pending_plans(&mut self) {
+        let Some((lol_dir, is_tencent)) = self.apply_target.clone() else {
+            return;
+        };
+        log::info!("start applying builds");
+        let plans = std::mem::take(&mut self.pending_plans);
+        let sources = plans.iter().map(|(s, _)| s.clone()).collect::<Vec<_>>();
+        let tx = self.start_progress(&sources, ApplyStage::Writing { done: 0, total: 0 });
+        let token = CancellationToken::new();
+        self.apply_cancel = Some(token.clone());
+
+        let promise = Promise::spawn_async(async move {
+            tokio::task::spawn_blocking(move || {
+                // one snapshot for every plan of the run
+                if plans.iter().any(|(_, plan)| !plan.is_empty()) {
+                    let writer = ItemSetWriter::for_game(&lol_dir, is_tencent);
+                    if let Err(err) = writer.back_up() {
+                        return vec![Err(
+                            anyhow::Error::new(err).context("Failed to back up the item sets")
+                        )];
+                    }
+                }
+                plans
+                    .iter()
+                    .map(|(source, plan)| {
+                        web::apply_plan(
+                            source,
+                            &lol_dir,
+                            is_tencent,
+                            plan,
+                            Some(tx.clone()),
+                            token.clone(),
+                        )
+                    })
+                    .collect()
+            })
+            .await
+            .unwrap_or_else(|err| vec![Err(err.into())])
+        });
+        self.apply_builds_promise = Some(promise);
+    }
+
+    fn render_apply_progress(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        if let Some(rx) = self.apply_progress_rx.as_mut() {
+            while let Ok(event) = rx.try_recv() {
+                if let Some((_, stage)) = self
+                    .apply_progress
+                    .iter_mut()
+                    .find(|(source, _)| source == &event.source)
+                {
+                    *stage = event.stage;
+                }
+            }
+        }
+        for (source, stage) in self.apply_progress.iter() {
+            ui.horizontal(|ui| {
+                ui.label(source);
+                let bar = match stage.fraction() {
+                    Some(fraction) => egui::ProgressBar::new(fraction),
+                    None => egui::ProgressBar::new(0.).animate(true),
+                };
+                ui.add(bar.text(stage.label()));
+            });
+        }
+        if let Some(token) = &self.apply_cancel {
+            if token.is_cancelled() {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label("Cancelling...");
+                });
+            } else if ui
+                .button("Cancel")
+                .on_hover_text("Stop applying, files already written are kept")
+                .clicked()
+            {
+                log::info!("cancel applying builds");
+                token.cancel();
+            }
+        }
+        ctx.request_repaint_after(std::time::Duration::from_millis(100));
+    }
+
+    fn collect_errors<T>(&mut self, results: Vec<anyhow::Result<T>>) {
+        self.apply_progress_rx = None;
+        self.apply_progress.clear();
+        self.apply_builds_errors = results
+            .iter()
+            .filter_map(|r| r.as_ref().err())
+            .filter(|err| !cancel::is_cancelled(err))
+            .map(|err| format!("{:#}", err))
+            .collect();
+        if self.apply_cancel.take().is_some_and(|t| t.is_cancelled()) {
+            self.apply_builds_errors
+                .insert(0, String::from("Applying builds was cancelled"));
+        }
+    }
+
+    /// Lists every file applying would create, overwrite or delete.
+    fn render_plan_preview(&mut self, ui: &mut egui::Ui) {
+        ui.label(egui::RichText::new("Review changes").strong());
+        egui::ScrollArea::vertical()
+            .max_height(240.)
+            .show(ui, |ui| {
+                for (source, plan) in self.pending_plans.iter() {
+                    ui.collapsing(format!("{source}: {}", plan.summary()), |ui| {
+                        for change in plan.changes.iter() {
+                            let name = change
+                                .path
+                                .file_name()
+                                .map(|n| n.to_string_lossy().to_string())
+                                .unwrap_or_default();
+                            let size = match (change.old_size, change.new_size) {
+                                (Some(old), Some(new)) => format!("{old} → {new} bytes"),
+                                (None, Some(new)) => format!("{new} bytes"),
+                                (Some(old), None) => format!("{old} bytes"),
+                                (None, None) => String::new(),
+                            };
+                            let text = format!("{} {name} ({size})", change.kind);
+                            if change.diff.is_empty() {
+                                ui.label(text).on_hover_text(change.path.to_string_lossy());
+                            } else {
+                                ui.collapsing(text, |ui| {
+                                    ui.monospace(&change.diff);
+                                });
+                            }
+                        }
+                        if !plan.item_changes.is_empty() {
+                            let title =
+                                format!("{} items not in this patch", plan.item_changes.len());
+                            ui.collapsing(title, |ui| {
+                                for change in plan.item_changes.iter() {
+                                    if &change.source == source {
+                                        ui.label(change.to_string());
+                                    } else {
+                                        ui.label(format!("{}: {change}", change.source));
+                                    }
+                                }
+                            });
+                        }
+                    });
+                }
+            });
+
+        let changes = self
+            .pending_plans
+            .iter()
+            .map(|(_, plan)| plan.changes.len())
+            .sum::<usize>();
+        ui.horizontal(|ui| {
+            if changes == 0 {
+                ui.label("Item sets are up to date.");
+                if ui.button("Close").clicked() {
+                    self.pending_plans.clear();
+                }
+            } else {
+                if ui
+                    .button(format!("Write {changes} changes"))
+                    .on_hover_text("Write the item sets into the game folder")
+                    .clicked()
+                {
+                    self.write_pending_plans();
+                }
+                if ui.button("Discard").clicked() {
+                    self.pending_plans.clear();
+                }
+            }
+        });
+    }
 }
 
 impl eframe::App for SourceWindow {
@@ -68,6 +877,27 @@ impl eframe::App for SourceWindow {
             if let Some(handle) = &self.lcu_task_handle {
                 handle.abort();
             }
+            if let Some(handle) = &self.local_watch_handle {
+                handle.abort();
+            }
+            if let Some(token) = &self.apply_cancel {
+                token.cancel();
+            }
+        }
+
+        {
+            let mut ui_ctx = self.rune_viewport_ctx.lock().unwrap();
+            if ui_ctx.is_none() {
+                *ui_ctx = Some(ctx.clone());
+            }
+        }
+
+        self.update_client_patch();
+
+        let local_generation = local_source::generation();
+        if self.local_generation != local_generation {
+            self.local_generation = local_generation;
+            self.sources_promise = None;
         }
 
         if self.champion_id.read().unwrap().is_some() {
@@ -79,6 +909,7 @@ impl eframe::App for SourceWindow {
             let show_rune_viewport = self.show_rune_viewport.clone();
             let lcu_auth = self.lcu_auth.clone();
             let champion_id = self.champion_id.clone();
+            let config = self.config.clone();
 
             ctx.show_viewport_deferred(
                 egui::ViewportId::from_hash_of("runes_window"),
@@ -97,6 +928,7 @@ impl eframe::App for SourceWindow {
                         rune_ui_state.clone(),
                         lcu_auth.clone(),
                         champion_id.clone(),
+                        config.clone(),
                     );
 
                     if ctx.input(|i| i.viewport().close_requested()) {
@@ -106,6 +938,8 @@ impl eframe::App for SourceWindow {
             );
         }
 
+        self.render_update_banner(ctx);
+
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.vertical_centered(|ui| {
                 ui.label(
@@ -129,15 +963,30 @@ impl eframe::App for SourceWindow {
                         Some(Ok(list)) => {
                             self.sources = list.clone();
 
+                            if self.package_status_promise.is_none() && !cache::is_offline() {
+                                let sources =
+                                    list.iter().map(|s| s.value.clone()).collect::<Vec<_>>();
+                                self.package_status_promise =
+                                    Some(Promise::spawn_async(check_package_statuses(sources)));
+                            }
+                            let statuses = self
+                                .package_status_promise
+                                .as_ref()
+                                .and_then(|p| p.ready())
+                                .cloned()
+                                .unwrap_or_default();
+
                             let mut indexes = list
                                 .iter()
                                 .map(|s| self.selected_sources.iter().any(|x| x == &s.value))
                                 .collect::<Vec<bool>>();
+                            let mut selection_changed = false;
                             for (index, checked) in indexes.iter_mut().enumerate() {
                                 let item = &list[index];
 
                                 ui.horizontal(|ui| {
                                     if ui.checkbox(checked, "").changed() {
+                                        selection_changed = true;
                                         if *checked {
                                             self.selected_sources.push(item.value.clone());
                                         } else {
@@ -154,6 +1003,7 @@ impl eframe::App for SourceWindow {
                                         )
                                         .clicked()
                                     {
+                                        selection_changed = true;
                                         if *checked {
                                             self.selected_sources.retain(|x| x != &item.value);
                                         } else {
@@ -173,21 +1023,172 @@ impl eframe::App for SourceWindow {
                                         ui.image(egui::include_image!("../../../assets/sr.png"))
                                             .on_hover_text("Summoner's Rift");
                                     }
+
+                                    if let Some((
+                                        PackageStatus::UpdateAvailable { installed, latest },
+                                        _,
+                                    )) = statuses.get(&item.value)
+                                    {
+                                        ui.small("update available").on_hover_text(format!(
+                                            "Downloaded {installed}, latest is {latest}"
+                                        ));
+                                    }
+                                    if let Some(describe) =
+                                        statuses.get(&item.value).and_then(|(_, v)| {
+                                            Staleness::of(v, self.client_patch).describe()
+                                        })
+                                    {
+                                        ui.colored_label(
+                                            ui.visuals().warn_fg_color,
+                                            egui::RichText::new("outdated").small(),
+                                        )
+                                        .on_hover_text(describe);
+                                    }
                                 });
                             }
+                            if selection_changed {
+                                self.save_selected_sources();
+                            }
                         }
                         Some(Err(err)) => {
-                            ui.label(format!("Failed to fetch sources: {:?}", err));
+                            ui.colored_label(
+                                ui.visuals().error_fg_color,
+                                format!("Failed to fetch sources: {:#}", err),
+                            );
                         }
                     },
                     None => {
                         let promise =
-                            Promise::spawn_async(async move { web::fetch_sources().await });
+                            Promise::spawn_async(async move { provider::list_sources().await });
                         self.sources_promise = Some(promise);
                     }
                 };
             });
 
+            if self.selected_sources.len() > 1 {
+                ui.collapsing("Source priority", |ui| {
+                    self.render_source_priority(ui);
+                });
+            }
+
+            ui.add_space(8.);
+            ui.horizontal(|ui| {
+                let mut offline = self.config.lock().unwrap().offline_mode;
+                ui.label("Offline mode")
+                    .on_hover_text("Only use sources, champions and runes cached on disk");
+                if toggle_ui::make_toggle(ui, &mut offline).changed() {
+                    self.config.lock().unwrap().set_offline_mode(offline);
+                    self.sources_promise = None;
+                }
+            });
+
+            ui.collapsing("Local builds", |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Folder");
+                    ui.text_edit_singleline(&mut self.local_builds_dir)
+                        .on_hover_text("One JSON file per champion, reloaded when they change");
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Label");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.local_builds_label)
+                            .hint_text(local_source::DEFAULT_LABEL),
+                    );
+                });
+                if ui.button("Save").clicked() {
+                    self.config
+                        .lock()
+                        .unwrap()
+                        .set_local_builds(&self.local_builds_dir, &self.local_builds_label);
+                    self.setup_local_source();
+                    self.sources_promise = None;
+                }
+            });
+
+            ui.collapsing("Filters", |ui| {
+                let filter = &mut self.build_filter;
+                egui::Grid::new("build_filter").show(ui, |ui| {
+                    ui.label("Min. pick count");
+                    ui.add(egui::DragValue::new(&mut filter.min_pick_count).speed(10));
+                    ui.end_row();
+                    ui.label("Min. win rate");
+                    ui.add(
+                        egui::DragValue::new(&mut filter.min_win_rate)
+                            .clamp_range(0.0..=100.0)
+                            .speed(0.1)
+                            .suffix("%"),
+                    );
+                    ui.end_row();
+                    ui.label("Max. item sets")
+                        .on_hover_text("Per champion and position, 0 for no limit");
+                    ui.add(egui::DragValue::new(&mut filter.max_item_sets));
+                    ui.end_row();
+                    ui.label("Max. rune pages")
+                        .on_hover_text("Shown per champion, 0 for no limit");
+                    ui.add(egui::DragValue::new(&mut filter.max_rune_pages));
+                    ui.end_row();
+                });
+                if ui.button("Save").clicked() {
+                    self.config
+                        .lock()
+                        .unwrap()
+                        .set_build_filter(self.build_filter);
+                }
+            });
+
+            ui.collapsing("Item set labels", |ui| {
+                self.render_item_set_labels(ui);
+            });
+
+            ui.collapsing("Champion pool", |ui| {
+                self.render_champion_pool(ui);
+            });
+
+            ui.collapsing("Updates", |ui| {
+                let (mut skip, mut channel) = {
+                    let conf = self.config.lock().unwrap();
+                    (conf.skip_update_check, conf.update_channel)
+                };
+                let mut changed = false;
+                ui.horizontal(|ui| {
+                    let mut check = !skip;
+                    ui.label("Check for updates");
+                    if toggle_ui::make_toggle(ui, &mut check).changed() {
+                        skip = !check;
+                        changed = true;
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Channel");
+                    changed |= ui
+                        .radio_value(&mut channel, UpdateChannel::Stable, "Stable")
+                        .changed();
+                    changed |= ui
+                        .radio_value(&mut channel, UpdateChannel::Nightly, "Nightly")
+                        .on_hover_text("Include pre-releases")
+                        .changed();
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Releases");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.releases_api)
+                            .hint_text("https://api.github.com/repos/<owner>/<repo>"),
+                    )
+                    .on_hover_text("Where new versions are looked for, empty to never check");
+                    if ui.button("Save").clicked() {
+                        self.config
+                            .lock()
+                            .unwrap()
+                            .set_releases_api(&self.releases_api);
+                        changed = true;
+                    }
+                });
+                if changed {
+                    self.config.lock().unwrap().set_update_check(skip, channel);
+                    self.check_for_update();
+                }
+            });
+
             let lcu_auth = {
                 let auth: std::sync::RwLockReadGuard<'_, CommandLineOutput> =
                     self.lcu_auth.read().unwrap();
@@ -208,41 +1209,125 @@ impl eframe::App for SourceWindow {
                     });
                 });
             } else {
+                if let Some(promise) = self.plan_promise.take() {
+                    match promise.try_take() {
+                        Ok(results) => {
+                            log::info!("planning builds done");
+                            let (plans, errors): (Vec<_>, Vec<_>) =
+                                results.into_iter().partition(|(_, r)| r.is_ok());
+                            self.pending_plans = plans
+                                .into_iter()
+                                .filter_map(|(source, plan)| Some((source, plan.ok()?)))
+                                .collect();
+                            self.collect_errors(errors.into_iter().map(|(_, r)| r).collect());
+                        }
+                        Err(promise) => self.plan_promise = Some(promise),
+                    }
+                }
+
                 match &self.apply_builds_promise {
+                    _ if self.plan_promise.is_some() => self.render_apply_progress(ui, ctx),
                     Some(p) => match p.ready() {
-                        None => {
-                            ui.spinner();
-                        }
-                        Some(_results) => {
+                        None => self.render_apply_progress(ui, ctx),
+                        Some(_) => {
                             log::info!("apply builds done");
-                            self.apply_builds_promise = None;
+                            if let Some(Ok(results)) =
+                                self.apply_builds_promise.take().map(|p| p.try_take())
+                            {
+                                let changed: usize = results
+                                    .iter()
+                                    .filter_map(|r| r.as_ref().ok())
+                                    .map(|report| report.changelog.len())
+                                    .sum();
+                                if changed > 0 {
+                                    self.refresh_changelog();
+                                    self.changelog_message = Some(Ok(format!(
+                                        "{changed} builds changed since the last apply"
+                                    )));
+                                }
+                                self.collect_errors(results);
+                            }
+                            self.package_status_promise = None;
                         }
                     },
-                    None => {
-                        if ui
-                            .button("Apply Builds")
-                            .on_hover_text("Apply builds from selected sources")
-                            .clicked()
-                        {
-                            log::info!("start applying builds");
-
-                            let lol_dir = lcu_auth.dir.clone();
-                            let is_tencent = lcu_auth.is_tencent;
-                            let selected_sources = self.selected_sources.clone();
-
-                            let promise = Promise::spawn_async(async move {
-                                let tasks = selected_sources.iter().map(|source| {
-                                    web::download_tar_and_apply_for_source(
-                                        source,
-                                        Some(lol_dir.clone()),
-                                        is_tencent,
-                                    )
-                                });
-                                join_all(tasks).await
-                            });
-                            self.apply_builds_promise = Some(promise);
+                    None if !self.pending_plans.is_empty() => self.render_plan_preview(ui),
+                    None if !self.stale_apply_warning.is_empty() => {
+                        ui.colored_label(
+                            ui.visuals().warn_fg_color,
+                            format!(
+                                "{} more than one patch behind the client.",
+                                self.stale_apply_warning.join(", ")
+                            ),
+                        );
+                        let (mut apply, mut dismiss) = (false, false);
+                        ui.horizontal(|ui| {
+                            apply = ui.button("Apply anyway").clicked();
+                            dismiss = ui.button("Cancel").clicked();
+                        });
+                        if apply || dismiss {
+                            self.stale_apply_warning.clear();
+                        }
+                        if apply {
+                            self.start_apply(lcu_auth.dir.clone(), lcu_auth.is_tencent);
                         }
                     }
+                    None => {
+                        ui.horizontal(|ui| {
+                            if ui
+                                .button("Apply Builds")
+                                .on_hover_text("Apply builds from selected sources")
+                                .clicked()
+                            {
+                                self.stale_apply_warning = self
+                                    .selected_sources
+                                    .iter()
+                                    .filter(|source| self.staleness(source).needs_warning())
+                                    .cloned()
+                                    .collect();
+                                if self.stale_apply_warning.is_empty() {
+                                    self.start_apply(lcu_auth.dir.clone(), lcu_auth.is_tencent);
+                                }
+                            }
+
+                            let mut merge = self.config.lock().unwrap().merge_sources;
+                            ui.label("Merge sources").on_hover_text(
+                                "One item set per champion and position, in source order",
+                            );
+                            if toggle_ui::make_toggle(ui, &mut merge).changed() {
+                                self.config.lock().unwrap().set_merge_sources(merge);
+                            }
+                        });
+                    }
+                }
+            }
+
+            for err in self.apply_builds_errors.iter() {
+                ui.colored_label(ui.visuals().error_fg_color, err);
+            }
+
+            let idle = self.plan_promise.is_none()
+                && self.apply_builds_promise.is_none()
+                && self.pending_plans.is_empty();
+            if is_running && idle {
+                ui.add_space(4.);
+                if ui
+                    .button("Remove all ChampR builds")
+                    .on_hover_text("Delete the item sets ChampR wrote, yours are kept")
+                    .clicked()
+                {
+                    self.plan_removal(lcu_auth.dir.clone(), is_tencent);
+                }
+                let changelog = ui.collapsing("Changelog", |ui| {
+                    self.render_changelog(ui);
+                });
+                if changelog.header_response.clicked() {
+                    self.refresh_changelog();
+                }
+                let backups = ui.collapsing("Backups", |ui| {
+                    self.render_backups(ui, &lcu_auth.dir, is_tencent);
+                });
+                if backups.header_response.clicked() {
+                    self.refresh_backups(&lcu_auth.dir, is_tencent);
                 }
             }
 
@@ -297,6 +1382,34 @@ impl eframe::App for SourceWindow {
     }
 }
 
+/// The package status of each npm source, along with the patch its installed
+/// data is for.
+async fn check_package_statuses(sources: Vec<String>) -> HashMap<String, (PackageStatus, String)> {
+    let npm_dir = std::path::Path::new(packages::NPM_DIR);
+    let tasks = sources
+        .iter()
+        .filter(|source| provider::for_source(source).is_ok_and(|p| p.id() == "npm"))
+        .map(|source| async move {
+            let status = packages::check(endpoints::npm(), npm_dir, source).await;
+            (source.clone(), status)
+        });
+
+    join_all(tasks)
+        .await
+        .into_iter()
+        .filter_map(|(source, status)| match status {
+            Ok((status, installed)) => {
+                let data_version = installed.map(|p| p.source_version).unwrap_or_default();
+                Some((source, (status, data_version)))
+            }
+            Err(err) => {
+                log::warn!("check package status of {}: {}", &source, err);
+                None
+            }
+        })
+        .collect()
+}
+
 pub fn setup_custom_fonts(ctx: &egui::Context) {
     // Start with the default fonts (we will be adding to them rather than replacing them).
     let mut fonts = egui::FontDefinitions::default();
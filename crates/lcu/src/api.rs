@@ -8,8 +8,8 @@ use serde_json::Value;
 
 use crate::{
     builds::{ItemBuild, Rune},
+    fetch_error::{self, FetchError},
     lcu_error::LcuError,
-    web::FetchError,
 };
 
 lazy_static! {
@@ -30,16 +30,13 @@ pub fn make_client() -> &'static reqwest::Client {
 
 pub async fn make_get_request<T: DeserializeOwned>(endpoint: &String) -> Result<T, LcuError> {
     let client = make_client();
-    client
+    let resp = client
         .get(endpoint)
         .version(reqwest::Version::HTTP_2)
         .header(reqwest::header::ACCEPT, "application/json")
         .send()
-        .await
-        .map_err(LcuError::from)?
-        .json()
-        .await
-        .map_err(LcuError::from)
+        .await?;
+    Ok(fetch_error::read_json(resp).await?)
 }
 
 pub async fn get_session(auth_url: &String) -> Result<Option<i64>, LcuError> {
@@ -71,6 +68,11 @@ pub async fn get_session(auth_url: &String) -> Result<Option<i64>, LcuError> {
     Ok(None)
 }
 
+/// The version of the running game, e.g. `14.2.556.5763`.
+pub async fn get_game_version(auth_url: &String) -> Result<String, LcuError> {
+    make_get_request(&format!("{auth_url}/lol-patch/v1/game-version")).await
+}
+
 pub async fn apply_rune(endpoint: String, rune: Rune) -> Result<(), LcuError> {
     let runes: Value = make_get_request(&format!("{endpoint}/lol-perks/v1/pages")).await?;
 
@@ -87,21 +89,23 @@ pub async fn apply_rune(endpoint: String, rune: Rune) -> Result<(), LcuError> {
 
     let client = make_client();
     if id > 0 {
-        let _ = client
+        let resp = client
             .delete(format!("{endpoint}/lol-perks/v1/pages/{id}"))
             .version(reqwest::Version::HTTP_2)
             .header(reqwest::header::ACCEPT, "application/json")
             .send()
             .await?;
+        fetch_error::read_text(resp).await?;
     }
 
-    let _ = client
+    let resp = client
         .post(format!("{endpoint}/lol-perks/v1/pages"))
         .version(reqwest::Version::HTTP_2)
         .header(reqwest::header::ACCEPT, "application/json")
         .json(&rune)
         .send()
         .await?;
+    fetch_error::read_text(resp).await?;
     Ok(())
 }
 
@@ -114,15 +118,8 @@ pub async fn appy_rune_and_builds(
 }
 
 pub async fn get_rune_image(endpoint: String, icon_path: String) -> Result<Bytes, FetchError> {
-    let client = make_client();
     let url = format!("{endpoint}/lol-game-data/assets/v1/{icon_path}");
-    if let Ok(resp) = client.get(&url).send().await {
-        if let Ok(bytes) = resp.bytes().await {
-            return Ok(bytes);
-        }
-    }
-
-    Err(FetchError::Failed)
+    fetch_rune_image(&url).await
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -230,6 +227,20 @@ pub async fn list_available_champions(
     make_get_request(&url).await
 }
 
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GameItem {
+    pub id: i64,
+    #[serde(default)]
+    pub name: String,
+}
+
+/// Every item of the patch the client runs.
+pub async fn list_game_items(endpoint: &String) -> Result<Vec<GameItem>, LcuError> {
+    let url = format!("{endpoint}/lol-game-data/assets/v1/items.json");
+    make_get_request(&url).await
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Perk {
@@ -252,18 +263,12 @@ pub async fn list_all_perks(endpoint: &String) -> Result<Vec<Perk>, LcuError> {
 
 pub async fn fetch_rune_image(url: &String) -> Result<Bytes, FetchError> {
     let client = make_client();
-    match client.get(url).send().await.map_err(|_| FetchError::Failed) {
-        Ok(res) => {
-            if res.status().is_success() {
-                return res.bytes().await.map_err(|_| FetchError::Failed);
-            }
-            Err(FetchError::Failed)
-        }
-        Err(err) => {
-            println!("Error fetching rune image: {:?}", err);
-            Err(FetchError::Failed)
-        }
+    let res = client.get(url).send().await?;
+    if !res.status().is_success() {
+        let url = fetch_error::redact_url(res.url());
+        return Err(FetchError::status(&url, res.status().as_u16(), ""));
     }
+    Ok(res.bytes().await?)
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -0,0 +1,222 @@
+use std::{
+    fmt, fs,
+    io::{self, Read},
+    path::{Component, Path, PathBuf},
+};
+
+use bytes::Bytes;
+use flate2::read::GzDecoder;
+use tar::{Archive, EntryType};
+use tokio::sync::mpsc::Receiver;
+
+/// Used when the registry does not tell us how big a package is.
+pub const DEFAULT_MAX_UNPACKED_SIZE: u64 = 256 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArchiveError {
+    /// An absolute path or one with `..`, which could write outside the target directory.
+    UnsafePath(String),
+    /// Symlinks and hard links are never extracted.
+    Link(String),
+    UnsupportedEntry(String),
+    TooLarge {
+        limit: u64,
+    },
+    Io(String),
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveError::UnsafePath(path) => {
+                write!(
+                    f,
+                    "refusing to extract `{path}`, it points outside the package"
+                )
+            }
+            ArchiveError::Link(path) => write!(f, "refusing to extract link `{path}`"),
+            ArchiveError::UnsupportedEntry(path) => {
+                write!(f, "refusing to extract `{path}`, not a regular file")
+            }
+            ArchiveError::TooLarge { limit } => {
+                write!(f, "package unpacks to more than the expected {limit} bytes")
+            }
+            ArchiveError::Io(message) => write!(f, "extract package: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for ArchiveError {}
+
+impl From<io::Error> for ArchiveError {
+    fn from(err: io::Error) -> ArchiveError {
+        ArchiveError::Io(err.to_string())
+    }
+}
+
+/// Unpacks a gzipped tarball into `dest`, rejecting anything that could escape it.
+/// Returns the number of bytes written. The reader is consumed up to its end.
+pub fn unpack<R: Read>(reader: R, dest: &Path, max_size: u64) -> Result<u64, ArchiveError> {
+    let mut archive = Archive::new(GzDecoder::new(reader));
+    let mut total = 0u64;
+
+    fs::create_dir_all(dest)?;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let display = path.to_string_lossy().to_string();
+        let relative =
+            safe_relative_path(&path).ok_or(ArchiveError::UnsafePath(display.clone()))?;
+        let target = dest.join(relative);
+
+        match entry.header().entry_type() {
+            EntryType::Regular | EntryType::Continuous => {
+                total += entry.size();
+                if total > max_size {
+                    return Err(ArchiveError::TooLarge { limit: max_size });
+                }
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                let mut file = fs::File::create(&target)?;
+                io::copy(&mut entry, &mut file)?;
+            }
+            EntryType::Directory => fs::create_dir_all(&target)?,
+            EntryType::Symlink | EntryType::Link => return Err(ArchiveError::Link(display)),
+            EntryType::XGlobalHeader | EntryType::XHeader => {}
+            _ => return Err(ArchiveError::UnsupportedEntry(display)),
+        }
+    }
+
+    // read up to EOF, so a streaming caller hashes the padding and the gzip
+    // trailer after the end-of-archive marker as well
+    io::copy(&mut archive.into_inner(), &mut io::sink())?;
+    Ok(total)
+}
+
+fn safe_relative_path(path: &Path) -> Option<PathBuf> {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => out.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+
+    if out.as_os_str().is_empty() {
+        None
+    } else {
+        Some(out)
+    }
+}
+
+/// Moves everything extracted into `staging` over to `dest`, replacing what was there.
+pub fn promote(staging: &Path, dest: &Path) -> io::Result<()> {
+    for entry in fs::read_dir(staging)? {
+        let entry = entry?;
+        let target = dest.join(entry.file_name());
+        if target.is_dir() {
+            fs::remove_dir_all(&target)?;
+        } else if target.exists() {
+            fs::remove_file(&target)?;
+        }
+        fs::rename(entry.path(), target)?;
+    }
+    fs::remove_dir_all(staging)
+}
+
+/// Adapts chunks arriving from an async download into a blocking reader for `tar`.
+pub struct ChannelReader {
+    rx: Receiver<Bytes>,
+    buf: Bytes,
+}
+
+impl ChannelReader {
+    pub fn new(rx: Receiver<Bytes>) -> Self {
+        Self {
+            rx,
+            buf: Bytes::new(),
+        }
+    }
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.buf.is_empty() {
+            match self.rx.blocking_recv() {
+                Some(chunk) => self.buf = chunk,
+                None => return Ok(0),
+            }
+        }
+
+        let n = out.len().min(self.buf.len());
+        out[..n].copy_from_slice(&self.buf[..n]);
+        self.buf = self.buf.slice(n..);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server;
+
+    fn raw_tgz(name: &[u8], kind: EntryType, data: &[u8]) -> Vec<u8> {
+        let gz = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        let mut builder = tar::Builder::new(gz);
+        let mut header = tar::Header::new_old();
+        header.as_old_mut().name[..name.len()].copy_from_slice(name);
+        header.set_entry_type(kind);
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        if kind == EntryType::Symlink {
+            header.as_old_mut().linkname[..11].copy_from_slice(b"/etc/passwd");
+        }
+        header.set_cksum();
+        builder.append(&header, data).unwrap();
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[test]
+    fn rejects_malicious_tarballs() {
+        let dest = test_server::temp_dir("unpack");
+
+        let cases = [
+            raw_tgz(b"../evil.json", EntryType::Regular, b"{}"),
+            raw_tgz(b"package/../../evil.json", EntryType::Regular, b"{}"),
+            raw_tgz(b"/tmp/evil.json", EntryType::Regular, b"{}"),
+        ];
+        for tgz in cases.iter() {
+            assert!(matches!(
+                unpack(&tgz[..], &dest, DEFAULT_MAX_UNPACKED_SIZE),
+                Err(ArchiveError::UnsafePath(_))
+            ));
+        }
+
+        let link = raw_tgz(b"package/passwd", EntryType::Symlink, b"");
+        assert!(matches!(
+            unpack(&link[..], &dest, DEFAULT_MAX_UNPACKED_SIZE),
+            Err(ArchiveError::Link(_))
+        ));
+
+        let bomb = raw_tgz(b"package/big.json", EntryType::Regular, &[b' '; 4096]);
+        assert_eq!(
+            unpack(&bomb[..], &dest, 1024),
+            Err(ArchiveError::TooLarge { limit: 1024 })
+        );
+
+        assert!(!dest.parent().unwrap().join("evil.json").exists());
+        assert!(!dest.join("package/big.json").exists());
+    }
+
+    #[test]
+    fn unpacks_regular_packages() {
+        let dest = test_server::temp_dir("unpack");
+        let tgz =
+            test_server::make_tgz(&[("package/Ahri.json", b"[]"), ("package/a/b.json", b"{}")]);
+
+        assert_eq!(unpack(&tgz[..], &dest, 4), Ok(4));
+        assert_eq!(fs::read(dest.join("package/a/b.json")).unwrap(), b"{}");
+    }
+}
@@ -0,0 +1,271 @@
+//! Compressed snapshots of the `Config/Champions` folder, taken before ChampR
+//! writes to it, so hand-made item sets can always be brought back.
+
+use std::{
+    fs,
+    io::{self, BufReader, BufWriter},
+    path::{Path, PathBuf},
+    sync::RwLock,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use flate2::{write::GzEncoder, Compression};
+use kv_log_macro::info;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+
+use crate::archive;
+
+/// Stored next to `.settings.toml`.
+pub const BACKUP_DIR: &str = ".backups";
+
+const PREFIX: &str = "champions-";
+const SUFFIX: &str = ".tar.gz";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionPolicy {
+    /// How many snapshots are kept at most.
+    pub keep: usize,
+    /// Snapshots older than this are removed, except for the newest one.
+    pub max_age_days: u64,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            keep: 10,
+            max_age_days: 30,
+        }
+    }
+}
+
+lazy_static! {
+    static ref RETENTION: RwLock<RetentionPolicy> = RwLock::new(RetentionPolicy::default());
+}
+
+pub fn configure(policy: RetentionPolicy) {
+    *RETENTION.write().unwrap() = policy;
+}
+
+pub fn retention() -> RetentionPolicy {
+    *RETENTION.read().unwrap()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub path: PathBuf,
+    /// Unix timestamp in milliseconds.
+    pub created_at: u64,
+    pub size: u64,
+}
+
+impl Snapshot {
+    fn from_path(path: PathBuf) -> Option<Snapshot> {
+        let name = path.file_name()?.to_str()?;
+        let created_at = name
+            .strip_prefix(PREFIX)?
+            .strip_suffix(SUFFIX)?
+            .parse()
+            .ok()?;
+        let size = fs::metadata(&path).ok()?.len();
+        Some(Snapshot {
+            path,
+            created_at,
+            size,
+        })
+    }
+
+    /// `YYYY-MM-DD HH:MM:SS` in UTC.
+    pub fn created_label(&self) -> String {
+        utc_label(self.created_at)
+    }
+}
+
+/// A unix timestamp in milliseconds as `YYYY-MM-DD HH:MM:SS` in UTC.
+pub fn utc_label(millis: u64) -> String {
+    let secs = millis / 1000;
+    let (days, rem) = ((secs / 86400) as i64, secs % 86400);
+
+    // days since the epoch to a civil date, after Howard Hinnant
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+pub(crate) fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Archives `champions_dir` into `backup_dir`. Nothing is taken when the folder
+/// does not exist yet, as there is nothing to lose.
+pub fn snapshot(champions_dir: &Path, backup_dir: &Path) -> io::Result<Option<Snapshot>> {
+    if !champions_dir.is_dir() {
+        return Ok(None);
+    }
+    fs::create_dir_all(backup_dir)?;
+
+    let mut created_at = now_millis();
+    while backup_dir
+        .join(format!("{PREFIX}{created_at}{SUFFIX}"))
+        .exists()
+    {
+        created_at += 1;
+    }
+    let path = backup_dir.join(format!("{PREFIX}{created_at}{SUFFIX}"));
+    let tmp = path.with_extension("tmp");
+
+    let write = || -> io::Result<()> {
+        let file = BufWriter::new(fs::File::create(&tmp)?);
+        let mut tar = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+        // entries are added by name, `archive::unpack` rejects a `./` root
+        for entry in fs::read_dir(champions_dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                tar.append_dir_all(entry.file_name(), entry.path())?;
+            } else {
+                tar.append_path_with_name(entry.path(), entry.file_name())?;
+            }
+        }
+        tar.into_inner()?.finish()?;
+        fs::rename(&tmp, &path)
+    };
+    write().inspect_err(|_| {
+        let _ = fs::remove_file(&tmp);
+    })?;
+
+    let snapshot =
+        Snapshot::from_path(path).ok_or_else(|| io::Error::other("snapshot vanished"))?;
+    info!("backed up {:?} to {:?}", champions_dir, &snapshot.path);
+    Ok(Some(snapshot))
+}
+
+/// Every snapshot in `backup_dir`, newest first.
+pub fn list(backup_dir: &Path) -> io::Result<Vec<Snapshot>> {
+    let entries = match fs::read_dir(backup_dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err),
+    };
+    let mut snapshots = entries
+        .filter_map(Result::ok)
+        .filter_map(|e| Snapshot::from_path(e.path()))
+        .collect::<Vec<_>>();
+    snapshots.sort_by_key(|s| std::cmp::Reverse(s.created_at));
+    Ok(snapshots)
+}
+
+/// Deletes the snapshots `policy` no longer keeps, returns how many.
+pub fn prune(backup_dir: &Path, policy: RetentionPolicy) -> io::Result<usize> {
+    let max_age = Duration::from_secs(policy.max_age_days * 86400).as_millis() as u64;
+    let oldest = now_millis().saturating_sub(max_age);
+
+    let mut removed = 0;
+    for (idx, snapshot) in list(backup_dir)?.into_iter().enumerate() {
+        let keep = idx == 0 || (idx < policy.keep && snapshot.created_at >= oldest);
+        if !keep {
+            fs::remove_file(&snapshot.path)?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// Replaces `champions_dir` with the content of `from`. What is there now is
+/// snapshotted first, so a restore can be undone as well.
+pub fn restore(from: &Snapshot, champions_dir: &Path, backup_dir: &Path) -> io::Result<()> {
+    snapshot(champions_dir, backup_dir)?;
+
+    let mut staging = champions_dir.as_os_str().to_owned();
+    staging.push(format!(".restore-{}", rand::random::<u32>()));
+    let staging = PathBuf::from(staging);
+    let file = BufReader::new(fs::File::open(&from.path)?);
+    if let Err(err) = archive::unpack(file, &staging, archive::DEFAULT_MAX_UNPACKED_SIZE) {
+        let _ = fs::remove_dir_all(&staging);
+        return Err(io::Error::other(err));
+    }
+
+    let mut previous = champions_dir.as_os_str().to_owned();
+    previous.push(".previous");
+    let previous = PathBuf::from(previous);
+    if previous.exists() {
+        fs::remove_dir_all(&previous)?;
+    }
+    if champions_dir.exists() {
+        fs::rename(champions_dir, &previous)?;
+    }
+    if let Err(err) = fs::rename(&staging, champions_dir) {
+        let _ = fs::rename(&previous, champions_dir);
+        let _ = fs::remove_dir_all(&staging);
+        return Err(err);
+    }
+    let _ = fs::remove_dir_all(&previous);
+
+    info!("restored {:?} from {:?}", champions_dir, &from.path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server;
+
+    #[test]
+    fn snapshots_prunes_and_restores() {
+        let root = test_server::temp_dir("backup");
+        let champions = root.join("Config/Champions");
+        let backups = root.join("backups");
+        assert_eq!(snapshot(&champions, &backups).unwrap(), None);
+
+        fs::create_dir_all(champions.join("Ahri/Recommended")).unwrap();
+        fs::write(champions.join("Ahri/Recommended/mine.json"), "hand made").unwrap();
+        let first = snapshot(&champions, &backups).unwrap().unwrap();
+        assert_eq!(first.created_label().len(), 19);
+
+        fs::write(champions.join("Ahri/Recommended/mine.json"), "overwritten").unwrap();
+        fs::write(champions.join("Ahri/Recommended/champr.json"), "{}").unwrap();
+        restore(&first, &champions, &backups).unwrap();
+        assert_eq!(
+            fs::read_to_string(champions.join("Ahri/Recommended/mine.json")).unwrap(),
+            "hand made"
+        );
+        assert!(!champions.join("Ahri/Recommended/champr.json").exists());
+
+        let snapshots = list(&backups).unwrap();
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!(snapshots[1], first);
+
+        let policy = RetentionPolicy {
+            keep: 1,
+            max_age_days: 30,
+        };
+        assert_eq!(prune(&backups, policy).unwrap(), 1);
+        assert_eq!(list(&backups).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn labels_timestamps_in_utc() {
+        let snapshot = Snapshot {
+            path: PathBuf::new(),
+            created_at: 1_709_294_400_000,
+            size: 0,
+        };
+        assert_eq!(snapshot.created_label(), "2024-03-01 12:00:00");
+    }
+}
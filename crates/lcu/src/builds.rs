@@ -1,16 +1,23 @@
+use anyhow::Context;
 use futures::StreamExt;
 
 use kv_log_macro::info;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::{
-    fs,
-    io::Write,
-    path::Path,
+    fs, io,
     sync::{Arc, Mutex},
 };
 
-use crate::web::{self, ChampionsMap, FetchError};
+use crate::{
+    cancel::{self, CancellationToken},
+    item_sets::ItemSetWriter,
+    items,
+    pool::ChampionPool,
+    progress::Progress,
+    provider,
+    web::ChampionsMap,
+};
 
 pub type LogItem = (String, String);
 
@@ -22,7 +29,13 @@ pub struct BuildSection {
     pub version: String,
     pub official_version: String,
     pub pick_count: i64,
-    pub win_rate: String,
+    /// In percent.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_win_rate",
+        serialize_with = "serialize_win_rate"
+    )]
+    pub win_rate: Option<f64>,
     pub timestamp: i64,
     pub alias: String,
     pub name: String,
@@ -71,7 +84,13 @@ pub struct Rune {
     pub name: String,
     pub position: String,
     pub pick_count: u64,
-    pub win_rate: String,
+    /// In percent.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_win_rate",
+        serialize_with = "serialize_win_rate"
+    )]
+    pub win_rate: Option<f64>,
     pub primary_style_id: i64,
     pub sub_style_id: i64,
     pub selected_perk_ids: Vec<i64>,
@@ -84,87 +103,111 @@ pub fn empty_rune_type() -> String {
     String::new()
 }
 
-pub fn apply_builds_from_data(
-    sections: Vec<BuildSection>,
-    dir: &String,
-    source: &str,
-    champion_alias: &String,
-    is_tencent: bool,
-) {
-    use kv_log_macro as log;
+/// Reads a win rate such as `52.3%`, `52.3` or `0.523` as a percentage.
+pub fn parse_win_rate(value: &str) -> Option<f64> {
+    let value = value.trim();
+    match value.strip_suffix('%') {
+        Some(percent) => percent.trim().parse().ok(),
+        None => value.parse().ok().map(percent_of),
+    }
+    .filter(|rate: &f64| rate.is_finite())
+}
 
-    let folder = if is_tencent {
-        format!("{dir}/Game/Config/Champions")
+/// Sources give win rates as fractions or percentages, a win rate of 1% or
+/// less is taken as a fraction unless it is written with a `%`.
+fn percent_of(rate: f64) -> f64 {
+    if rate.abs() <= 1. {
+        rate * 100.
     } else {
-        format!("{dir}/Config/Champions")
-    };
-    let parent_dir = format!("{folder}/{champion_alias}/Recommended");
-    if let Err(err) = fs::create_dir_all(&parent_dir) {
-        log::error!("failed to create dir: {:?}", err);
+        rate
     }
+}
 
-    let source_name = source.replace('.', "_");
-    for (idx, b) in sections.iter().enumerate() {
-        let pos = &b.position;
-        for (iidx, item) in b.item_builds.iter().enumerate() {
-            let full_path =
-                format!("{parent_dir}/{source_name}_{champion_alias}_{pos}_{idx}_{iidx}.json");
-            let mut f = fs::File::create(&full_path).unwrap();
+fn deserialize_win_rate<'de, D>(deserializer: D) -> Result<Option<f64>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Ok(match Option::<Value>::deserialize(deserializer)? {
+        Some(Value::Number(rate)) => rate.as_f64().map(percent_of),
+        Some(Value::String(rate)) => parse_win_rate(&rate),
+        _ => None,
+    })
+}
 
-            let buf = serde_json::to_string_pretty(&item).unwrap();
-            f.write_all(buf[..].as_bytes()).unwrap();
+/// Writes win rates with a `%`, so that reading them back never takes a
+/// small percentage for a fraction.
+fn serialize_win_rate<S>(win_rate: &Option<f64>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    match win_rate {
+        Some(rate) => serializer.serialize_str(&format!("{rate}%")),
+        None => serializer.serialize_none(),
+    }
+}
 
-            log::info!("builds saved to: {}", &full_path);
-        }
+pub fn apply_builds_from_data(
+    sections: Vec<BuildSection>,
+    writer: &ItemSetWriter,
+    source: &str,
+    champion_alias: &str,
+) -> io::Result<()> {
+    write_item_sets(writer, source, champion_alias, &sections, true)
+}
+
+/// Writes the item sets of one champion, snapshotting the folder first when
+/// `back_up` is set and anything is about to change.
+fn write_item_sets(
+    writer: &ItemSetWriter,
+    source: &str,
+    champion_alias: &str,
+    sections: &[BuildSection],
+    back_up: bool,
+) -> io::Result<()> {
+    let mut sections = sections.to_vec();
+    writer.filter().filter_sections(&mut sections);
+    let item_changes =
+        items::shared().check_sections(source, &mut sections, &items::substitutions());
+    for change in item_changes.iter() {
+        info!("[{}] {}", source, change);
     }
+    let plan = writer.plan(source, champion_alias, &sections)?;
+    if plan.is_empty() {
+        return Ok(());
+    }
+    if back_up {
+        writer.back_up()?;
+    }
+    writer
+        .execute(&plan, &Progress::default(), &CancellationToken::new())
+        .into_result()?;
+
+    Ok(())
 }
 
 pub async fn apply_builds_from_source(
-    dir: &String,
-    source: &String,
-    champion: &String,
-    is_tencent: bool,
-) -> Result<(), FetchError> {
-    let sections = match web::list_builds_by_alias(source, champion).await {
-        Ok(s) => s,
-        Err(_) => {
-            return Err(FetchError::Failed);
-        }
-    };
-    apply_builds_from_data(sections, dir, source, champion, is_tencent);
+    writer: &ItemSetWriter,
+    source: &str,
+    champion: &str,
+) -> anyhow::Result<()> {
+    let sections = provider::list_builds(source, champion).await?;
+    apply_builds_from_data(sections, writer, source, champion)?;
 
     Ok(())
 }
 
+/// Writes the builds of `champion` from `source`, as served by its provider,
+/// with `writer`. No snapshot is taken, that is left to the caller; see
+/// `ItemSetWriter::back_up`.
 pub async fn fetch_and_apply(
-    dir: &String,
-    source: &String,
-    champion: &String,
-) -> Result<(), FetchError> {
-    let sections = match web::list_builds_by_alias(source, champion).await {
-        Ok(s) => s,
-        Err(_) => {
-            return Err(FetchError::Failed);
-        }
-    };
-
-    let parent_dir = format!("{dir}/{champion}/Recommended");
-    let _result = fs::create_dir_all(&parent_dir);
-
-    let source_name = source.replace('.', "_");
-    for (idx, b) in sections.iter().enumerate() {
-        let pos = &b.position;
-        for (iidx, item) in b.item_builds.iter().enumerate() {
-            let full_path =
-                format!("{parent_dir}/{source_name}_{champion}_{pos}_{idx}_{iidx}.json");
-            let mut f = fs::File::create(&full_path).unwrap();
-
-            let buf = serde_json::to_string_pretty(&item).unwrap();
-            f.write_all(buf[..].as_bytes()).unwrap();
-
-            info!("saved to: {}", &full_path);
-        }
-    }
+    writer: &ItemSetWriter,
+    source: &str,
+    champion: &str,
+    pool: &ChampionPool,
+) -> anyhow::Result<()> {
+    let mut sections = provider::list_builds(source, champion).await?;
+    pool.filter_sections(&mut sections);
+    write_item_sets(writer, source, champion, &sections, false)?;
 
     Ok(())
 }
@@ -172,32 +215,38 @@ pub async fn fetch_and_apply(
 pub async fn batch_apply(
     selected_sources: Vec<String>,
     champions_map: ChampionsMap,
-    dir: String,
-    is_tencent: bool,
+    writer: ItemSetWriter,
+    pool: ChampionPool,
     logs: Arc<Mutex<Vec<LogItem>>>,
-) -> Result<(), ()> {
+    cancel: CancellationToken,
+) -> anyhow::Result<()> {
     let mut tasks = vec![];
 
-    let folder = if is_tencent {
-        format!("{dir}/Game/Config/Champions")
-    } else {
-        format!("{dir}/Config/Champions")
-    };
-    if Path::new(&folder).exists() {
-        let _ = fs::remove_dir_all(&folder);
-    } else {
-        let _ = fs::create_dir_all(&folder);
-    }
+    // stale item sets of each source are deleted by the plan, the rest of the
+    // folder may hold sets the player made and is left alone
+    let _ = fs::create_dir_all(writer.dir());
+    // a single snapshot for the whole run, so it still holds the sets from
+    // before the run once every champion is written
+    writer.back_up().context("back up item sets")?;
 
+    let pool = &pool;
+    let writer = &writer;
     for (champion, _) in champions_map.iter() {
         for source in selected_sources.iter() {
             let source = source.clone();
             let logs = logs.clone();
-            let config_folder = folder.clone();
+            let cancel = cancel.clone();
 
             let task = async move {
+                if cancel.is_cancelled() {
+                    return;
+                }
+                if !pool.has_champion(champion) {
+                    // sets written earlier for champions outside the pool are kept
+                    return;
+                }
                 info!("[apply_builds] started {:?} {:?}", &source, &champion);
-                let r = fetch_and_apply(&config_folder, &source, champion).await;
+                let r = fetch_and_apply(writer, &source, champion, pool).await;
                 if r.is_ok() {
                     let mut logs = logs.lock().unwrap();
                     logs.push((source.clone(), champion.clone()));
@@ -215,7 +264,7 @@ pub async fn batch_apply(
         .collect::<Vec<()>>()
         .await;
 
-    Ok(())
+    Ok(cancel::check(&cancel)?)
 }
 
 #[derive(Default, Debug, Clone)]
@@ -226,8 +275,52 @@ mod tests {
     use super::*;
 
     #[tokio::test]
-    async fn apply_builds() -> Result<(), FetchError> {
-        let target = String::from(".test");
-        fetch_and_apply(&target, &String::from("op.gg"), &String::from("Rengar")).await
+    #[ignore = "fetches builds from the npm registry"]
+    async fn apply_builds() -> anyhow::Result<()> {
+        fetch_and_apply(
+            &ItemSetWriter::new(".test"),
+            &String::from("op.gg"),
+            &String::from("Rengar"),
+            &ChampionPool::all(),
+        )
+        .await
+    }
+
+    #[test]
+    fn reads_win_rates_in_any_notation() {
+        assert_eq!(parse_win_rate("52.3%"), Some(52.3));
+        assert_eq!(parse_win_rate(" 48 % "), Some(48.));
+        assert_eq!(parse_win_rate("0.5"), Some(50.));
+        assert_eq!(parse_win_rate("0.008"), Some(0.8));
+        assert_eq!(parse_win_rate("0.8%"), Some(0.8));
+        assert_eq!(parse_win_rate("1%"), Some(1.));
+        assert_eq!(parse_win_rate("n/a"), None);
+
+        let rune = |win_rate: &str| {
+            let json = format!(
+                r#"{{"alias": "Ahri", "name": "", "position": "mid", "pickCount": 10,
+                    "primaryStyleId": 8100, "subStyleId": 8200, "selectedPerkIds": []{win_rate}}}"#
+            );
+            serde_json::from_str::<Rune>(&json).unwrap().win_rate
+        };
+        assert_eq!(rune(r#", "winRate": "51.2%""#), Some(51.2));
+        assert_eq!(rune(r#", "winRate": 0.49"#), Some(49.));
+        assert_eq!(rune(r#", "winRate": "0.8%""#), Some(0.8));
+        assert_eq!(rune(r#", "winRate": "1%""#), Some(1.));
+        assert_eq!(rune(r#", "winRate": "0.008""#), Some(0.8));
+        assert_eq!(rune(r#", "winRate": null"#), None);
+        assert_eq!(rune(""), None);
+
+        for win_rate in [Some(0.8), Some(1.), Some(52.3), None] {
+            let rune = Rune {
+                win_rate,
+                ..Default::default()
+            };
+            let json = serde_json::to_string(&rune).unwrap();
+            assert_eq!(
+                serde_json::from_str::<Rune>(&json).unwrap().win_rate,
+                win_rate
+            );
+        }
     }
 }
@@ -0,0 +1,379 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use kv_log_macro::{info, warn};
+use lazy_static::lazy_static;
+use reqwest::{
+    header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+    StatusCode,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::fetch_error::{self, FetchError};
+
+pub const CACHE_DIR: &str = ".cache/http";
+
+lazy_static! {
+    static ref SHARED: HttpCache = HttpCache::new(CACHE_DIR);
+    static ref CLIENT: reqwest::Client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(15))
+        .build()
+        .unwrap();
+    static ref KEY_LOCKS: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>> =
+        Mutex::new(HashMap::new());
+}
+
+/// When offline, only cached responses are served and the network is never touched.
+pub fn set_offline(offline: bool) {
+    SHARED.set_offline(offline);
+}
+
+pub fn is_offline() -> bool {
+    SHARED.is_offline()
+}
+
+pub fn shared() -> &'static HttpCache {
+    &SHARED
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CachePolicy {
+    /// How long a response is used without asking the server.
+    pub ttl: Duration,
+    /// How long past `ttl` a response is still served while it is revalidated in the background.
+    pub stale_while_revalidate: Duration,
+}
+
+impl CachePolicy {
+    pub const fn new(ttl_secs: u64, stale_secs: u64) -> Self {
+        Self {
+            ttl: Duration::from_secs(ttl_secs),
+            stale_while_revalidate: Duration::from_secs(stale_secs),
+        }
+    }
+}
+
+pub const SOURCES_POLICY: CachePolicy = CachePolicy::new(60 * 60, 7 * 24 * 60 * 60);
+pub const GAME_DATA_POLICY: CachePolicy = CachePolicy::new(12 * 60 * 60, 14 * 24 * 60 * 60);
+pub const BUILDS_POLICY: CachePolicy = CachePolicy::new(60 * 60, 24 * 60 * 60);
+/// Patch lists change every couple of weeks, but should be picked up within the hour.
+pub const VERSIONS_POLICY: CachePolicy = CachePolicy::new(60 * 60, 14 * 24 * 60 * 60);
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub fetched_at: u64,
+    pub body: String,
+}
+
+impl CacheEntry {
+    pub fn age(&self) -> Duration {
+        Duration::from_secs(now_secs().saturating_sub(self.fetched_at))
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self) -> Result<T, FetchError> {
+        fetch_error::decode_json(&self.url, &self.body)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpCache {
+    dir: PathBuf,
+    offline: Arc<AtomicBool>,
+}
+
+impl HttpCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            offline: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn set_offline(&self, offline: bool) {
+        self.offline.store(offline, Ordering::Relaxed);
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.offline.load(Ordering::Relaxed)
+    }
+
+    pub async fn get_json<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        policy: CachePolicy,
+    ) -> Result<T, FetchError> {
+        let entry = self.get(url, &[url.to_string()], policy).await?;
+        entry.decode()
+    }
+
+    /// Looks up `key`, asking `urls` in order whenever the entry needs (re)validation.
+    /// The key is independent of the url so that switching mirrors keeps the cached data.
+    pub async fn get(
+        &self,
+        key: &str,
+        urls: &[String],
+        policy: CachePolicy,
+    ) -> Result<CacheEntry, FetchError> {
+        let lock = KeyLock::new(key);
+        let _guard = lock.lock().await;
+
+        let cached = self.read_entry(key);
+        if self.is_offline() {
+            return match cached {
+                Some(entry) => Ok(entry),
+                None => {
+                    warn!("[cache] offline and nothing cached for {}", key);
+                    Err(FetchError::Offline(key.to_string()))
+                }
+            };
+        }
+
+        match cached {
+            Some(entry) if entry.age() < policy.ttl => Ok(entry),
+            Some(entry) if entry.age() < policy.ttl + policy.stale_while_revalidate => {
+                let cache = self.clone();
+                let key = key.to_string();
+                let urls = urls.to_vec();
+                let stale = entry.clone();
+                tokio::spawn(async move {
+                    let lock = KeyLock::new(&key);
+                    let _guard = lock.lock().await;
+                    if let Err(err) = cache.revalidate(&key, &urls, Some(stale)).await {
+                        warn!("[cache] background revalidation of {}: {:?}", &key, err);
+                    }
+                });
+                Ok(entry)
+            }
+            Some(entry) => match self.revalidate(key, urls, Some(entry.clone())).await {
+                Ok(fresh) => Ok(fresh),
+                Err(err) => {
+                    warn!("[cache] serving stale {}: {:?}", key, err);
+                    Ok(entry)
+                }
+            },
+            None => self.revalidate(key, urls, None).await,
+        }
+    }
+
+    async fn revalidate(
+        &self,
+        key: &str,
+        urls: &[String],
+        cached: Option<CacheEntry>,
+    ) -> Result<CacheEntry, FetchError> {
+        let mut last_err = FetchError::NotFound(key.to_string());
+        for url in urls {
+            match self.revalidate_url(url, cached.clone()).await {
+                Ok(entry) => {
+                    if let Err(err) = self.write_entry(key, &entry) {
+                        warn!("[cache] write {}: {:?}", key, err);
+                    }
+                    info!("[cache] refreshed {} from {}", key, url);
+                    return Ok(entry);
+                }
+                Err(err) => {
+                    warn!("[cache] {} failed, trying next: {}", url, err);
+                    last_err = err;
+                }
+            }
+        }
+
+        Err(last_err)
+    }
+
+    /// Sends a conditional request for `url`.
+    async fn revalidate_url(
+        &self,
+        url: &str,
+        cached: Option<CacheEntry>,
+    ) -> Result<CacheEntry, FetchError> {
+        let mut req = CLIENT.get(url);
+        if let Some(entry) = &cached {
+            if let Some(etag) = &entry.etag {
+                req = req.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &entry.last_modified {
+                req = req.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+
+        let resp = req.send().await?;
+
+        match (resp.status(), cached) {
+            (StatusCode::NOT_MODIFIED, Some(entry)) => Ok(CacheEntry {
+                url: url.to_string(),
+                fetched_at: now_secs(),
+                ..entry
+            }),
+            (status, _) if status.is_success() => {
+                let header = |name| {
+                    resp.headers()
+                        .get(name)
+                        .and_then(|v: &reqwest::header::HeaderValue| v.to_str().ok())
+                        .map(String::from)
+                };
+                let etag = header(ETAG);
+                let last_modified = header(LAST_MODIFIED);
+                let body = resp.text().await?;
+                Ok(CacheEntry {
+                    url: url.to_string(),
+                    etag,
+                    last_modified,
+                    fetched_at: now_secs(),
+                    body,
+                })
+            }
+            (status, _) => {
+                let body = resp.text().await.unwrap_or_default();
+                Err(FetchError::status(url, status.as_u16(), &body))
+            }
+        }
+    }
+
+    pub fn read_entry(&self, key: &str) -> Option<CacheEntry> {
+        let content = fs::read_to_string(self.entry_path(key)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    fn write_entry(&self, key: &str, entry: &CacheEntry) -> anyhow::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.entry_path(key);
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec(entry)?)?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    /// Named by a hash of `key`, so that no two keys share a file.
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir
+            .join(format!("{:x}.json", Sha256::digest(key.as_bytes())))
+    }
+}
+
+/// Serializes the work on one key. Its entry in `KEY_LOCKS` is dropped once
+/// nobody holds or waits for it.
+struct KeyLock {
+    key: String,
+    lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl KeyLock {
+    fn new(key: &str) -> Self {
+        let lock = KEY_LOCKS
+            .lock()
+            .unwrap()
+            .entry(key.to_string())
+            .or_default()
+            .clone();
+        Self {
+            key: key.to_string(),
+            lock,
+        }
+    }
+
+    async fn lock(&self) -> tokio::sync::MutexGuard<'_, ()> {
+        self.lock.lock().await
+    }
+}
+
+impl Drop for KeyLock {
+    fn drop(&mut self) {
+        let mut locks = KEY_LOCKS.lock().unwrap();
+        // one reference is the map's, the other ours
+        if Arc::strong_count(&self.lock) <= 2 {
+            locks.remove(&self.key);
+        }
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{self, Response};
+
+    #[tokio::test]
+    async fn revalidates_with_etag_and_serves_offline() {
+        let server = test_server::serve(|req| {
+            if req.header("if-none-match").map(String::as_str) == Some("\"v1\"") {
+                Response::status(304)
+            } else {
+                Response::ok(r#"["a","b"]"#).with_header("etag", "\"v1\"")
+            }
+        })
+        .await;
+        let cache = HttpCache::new(test_server::temp_dir("cache"));
+        let url = server.url("/api/sources");
+        let expired = CachePolicy::new(0, 0);
+
+        let first: Vec<String> = cache.get_json(&url, expired).await.unwrap();
+        let second: Vec<String> = cache.get_json(&url, expired).await.unwrap();
+        assert_eq!(first, second);
+        assert_eq!(server.hits("/api/sources"), 2);
+        assert_eq!(
+            server.requests.lock().unwrap()[1].header("if-none-match"),
+            Some(&String::from("\"v1\""))
+        );
+
+        let fresh = CachePolicy::new(3600, 0);
+        let _: Vec<String> = cache.get_json(&url, fresh).await.unwrap();
+        assert_eq!(server.hits("/api/sources"), 2);
+
+        cache.set_offline(true);
+        let offline: Vec<String> = cache.get_json(&url, expired).await.unwrap();
+        let missing = cache
+            .get_json::<Vec<String>>(&server.url("/api/other"), expired)
+            .await;
+        assert_eq!(offline, first);
+        assert!(missing.is_err());
+        assert_eq!(server.hits("/api/sources"), 2);
+        assert!(!KEY_LOCKS.lock().unwrap().contains_key(&url));
+    }
+
+    #[test]
+    fn keeps_similar_keys_apart() {
+        let cache = HttpCache::new("cache");
+        assert_ne!(cache.entry_path("a/b-c"), cache.entry_path("a_b/c"));
+        assert_ne!(
+            cache.entry_path("https://x/?a=1&b"),
+            cache.entry_path("https://x/?a=1_b")
+        );
+    }
+
+    #[tokio::test]
+    async fn serves_stale_when_server_is_down() {
+        let server = test_server::serve(|req| {
+            if req.path == "/runes" && !req.headers.contains_key("if-none-match") {
+                Response::ok("[1,2,3]").with_header("etag", "\"r\"")
+            } else {
+                Response::status(500)
+            }
+        })
+        .await;
+        let cache = HttpCache::new(test_server::temp_dir("cache"));
+        let url = server.url("/runes");
+
+        let _: Vec<u8> = cache.get_json(&url, CachePolicy::new(0, 0)).await.unwrap();
+        let stale: Vec<u8> = cache.get_json(&url, CachePolicy::new(0, 0)).await.unwrap();
+        assert_eq!(stale, vec![1, 2, 3]);
+    }
+}
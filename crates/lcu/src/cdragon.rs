@@ -0,0 +1,315 @@
+//! Static game data from Community Dragon, which Data Dragon falls back to.
+//! Its files are the game client's own and are mapped onto the Data Dragon
+//! types here.
+
+use std::{collections::HashMap, env};
+
+use lazy_static::lazy_static;
+use serde::{de::DeserializeOwned, Deserialize};
+
+use crate::{
+    cache::{CachePolicy, HttpCache, GAME_DATA_POLICY, VERSIONS_POLICY},
+    ddragon::{ItemGold, ItemInfo, ItemsMap, SummonerSpell, SummonerSpellsMap},
+    endpoints::{self, Mirrors},
+    fetch_error::FetchError,
+    web::{ChampInfo, ChampionsMap, DataDragonRune, Image, Slot, SlotRune},
+};
+
+pub const CDRAGON_URL_ENV: &str = "CHAMPR_CDRAGON_URL";
+pub const DEFAULT_CDRAGON_URLS: &[&str] = &["https://raw.communitydragon.org"];
+
+/// Prefix of the http cache keys, Community Dragon paths may look like those
+/// of other hosts sharing the cache.
+const CACHE_KEY_PREFIX: &str = "cdragon:";
+
+/// Where the client serves its assets from, icon paths are relative to it.
+const ASSETS_PREFIX: &str = "/lol-game-data/assets/v1/";
+
+lazy_static! {
+    static ref CDRAGON: Mirrors = Mirrors::new(DEFAULT_CDRAGON_URLS);
+}
+
+/// `CHAMPR_CDRAGON_URL` replaces the Community Dragon host.
+pub fn configure() {
+    let from_env = env::var(CDRAGON_URL_ENV)
+        .map(|v| endpoints::parse_list(&v))
+        .unwrap_or_default();
+    if !from_env.is_empty() {
+        CDRAGON.set(from_env);
+    }
+}
+
+pub fn mirrors() -> &'static Mirrors {
+    &CDRAGON
+}
+
+#[derive(Deserialize)]
+struct ContentMetadata {
+    version: String,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct ChampionSummary {
+    id: i64,
+    name: String,
+    alias: String,
+    roles: Vec<String>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct Perk {
+    id: u64,
+    name: String,
+    short_desc: String,
+    long_desc: String,
+    icon_path: String,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct PerkStyles {
+    styles: Vec<PerkStyle>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct PerkStyle {
+    id: u64,
+    name: String,
+    icon_path: String,
+    slots: Vec<PerkSlot>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct PerkSlot {
+    #[serde(rename = "type")]
+    kind: String,
+    perks: Vec<u64>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct Item {
+    id: i64,
+    name: String,
+    in_store: bool,
+    from: Vec<i64>,
+    to: Vec<i64>,
+    categories: Vec<String>,
+    price: i64,
+    price_total: i64,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct Spell {
+    id: i64,
+    name: String,
+    description: String,
+    game_modes: Vec<String>,
+}
+
+/// `14.2` of a Data Dragon version such as `14.2.1`.
+fn patch(version: &str) -> String {
+    let parts = version.split('.').take(2).collect::<Vec<_>>();
+    if parts.len() < 2 {
+        String::from("latest")
+    } else {
+        parts.join(".")
+    }
+}
+
+/// Community Dragon spells locales in lower case and calls `en_US` `default`.
+fn locale_dir(locale: &str) -> String {
+    match locale.to_lowercase().as_str() {
+        "" | "en_us" => String::from("default"),
+        locale => locale.to_string(),
+    }
+}
+
+/// `perk-images/Styles/7200_Domination.png` of a client asset path.
+fn asset(icon_path: &str) -> String {
+    match icon_path.get(..ASSETS_PREFIX.len()) {
+        Some(prefix) if prefix.eq_ignore_ascii_case(ASSETS_PREFIX) => {
+            icon_path[ASSETS_PREFIX.len()..].to_string()
+        }
+        _ => icon_path.trim_start_matches('/').to_string(),
+    }
+}
+
+/// `Domination` of `.../7200_Domination.png`, Community Dragon has no keys.
+fn key_of(icon_path: &str) -> String {
+    let file = icon_path.rsplit('/').next().unwrap_or_default();
+    let stem = file.split('.').next().unwrap_or_default();
+    stem.rsplit('_').next().unwrap_or_default().to_string()
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    chars
+        .next()
+        .map(|first| first.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}
+
+pub struct CommunityDragon<'a> {
+    mirrors: &'a Mirrors,
+    cache: &'a HttpCache,
+    locale: String,
+}
+
+impl<'a> CommunityDragon<'a> {
+    pub fn new(mirrors: &'a Mirrors, cache: &'a HttpCache, locale: &str) -> Self {
+        Self {
+            mirrors,
+            cache,
+            locale: locale_dir(locale),
+        }
+    }
+
+    async fn get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        policy: CachePolicy,
+    ) -> Result<T, FetchError> {
+        let key = format!("{CACHE_KEY_PREFIX}{path}");
+        self.mirrors
+            .get_cached_json_in(self.cache, &key, path, policy)
+            .await
+    }
+
+    async fn game_data<T: DeserializeOwned>(
+        &self,
+        version: &str,
+        file: &str,
+    ) -> Result<T, FetchError> {
+        let path = format!(
+            "/{}/plugins/rcp-be-lol-game-data/global/{}/v1/{file}",
+            patch(version),
+            &self.locale
+        );
+        self.get(&path, GAME_DATA_POLICY).await
+    }
+
+    /// The version of the client files on the current patch.
+    pub async fn latest_version(&self) -> Result<String, FetchError> {
+        let metadata: ContentMetadata = self
+            .get("/latest/content-metadata.json", VERSIONS_POLICY)
+            .await?;
+        Ok(metadata.version)
+    }
+
+    /// The champions by alias, without titles; only `image.full` is known of
+    /// the images.
+    pub async fn champions(&self, version: &str) -> Result<ChampionsMap, FetchError> {
+        let summaries: Vec<ChampionSummary> =
+            self.game_data(version, "champion-summary.json").await?;
+        Ok(summaries
+            .into_iter()
+            // `-1` stands for no champion
+            .filter(|champion| champion.id > 0)
+            .map(|champion| {
+                let info = ChampInfo {
+                    version: version.to_string(),
+                    id: champion.alias.clone(),
+                    key: champion.id.to_string(),
+                    name: champion.name,
+                    title: String::new(),
+                    image: Image {
+                        full: format!("{}.png", champion.alias),
+                        ..Default::default()
+                    },
+                    tags: champion.roles.iter().map(|r| capitalize(r)).collect(),
+                };
+                (champion.alias, info)
+            })
+            .collect())
+    }
+
+    /// The rune trees without the stat shards, as in Data Dragon.
+    pub async fn runes(&self, version: &str) -> Result<Vec<DataDragonRune>, FetchError> {
+        let perks: Vec<Perk> = self.game_data(version, "perks.json").await?;
+        let styles: PerkStyles = self.game_data(version, "perkstyles.json").await?;
+        let perks = perks
+            .into_iter()
+            .map(|perk| (perk.id, perk))
+            .collect::<HashMap<_, _>>();
+
+        Ok(styles
+            .styles
+            .into_iter()
+            .map(|style| DataDragonRune {
+                id: style.id,
+                key: key_of(&style.icon_path),
+                icon: asset(&style.icon_path),
+                name: style.name,
+                slots: style
+                    .slots
+                    .iter()
+                    .filter(|slot| slot.kind != "kStatMod")
+                    .map(|slot| Slot {
+                        runes: slot
+                            .perks
+                            .iter()
+                            .filter_map(|id| perks.get(id))
+                            .map(|perk| SlotRune {
+                                id: perk.id,
+                                key: key_of(&perk.icon_path),
+                                icon: asset(&perk.icon_path),
+                                name: perk.name.clone(),
+                                short_desc: perk.short_desc.clone(),
+                                long_desc: perk.long_desc.clone(),
+                            })
+                            .collect(),
+                    })
+                    .collect(),
+            })
+            .collect())
+    }
+
+    /// The items by id. Sell prices and maps are not known.
+    pub async fn items(&self, version: &str) -> Result<ItemsMap, FetchError> {
+        let items: Vec<Item> = self.game_data(version, "items.json").await?;
+        Ok(items
+            .into_iter()
+            .map(|item| {
+                let info = ItemInfo {
+                    name: item.name,
+                    gold: ItemGold {
+                        base: item.price,
+                        total: item.price_total,
+                        sell: 0,
+                        purchasable: item.in_store,
+                    },
+                    tags: item.categories,
+                    into: item.to.iter().map(|id| id.to_string()).collect(),
+                    from: item.from.iter().map(|id| id.to_string()).collect(),
+                    ..Default::default()
+                };
+                (item.id.to_string(), info)
+            })
+            .collect())
+    }
+
+    /// The summoner spells by their numeric key, which is also their id.
+    pub async fn summoner_spells(&self, version: &str) -> Result<SummonerSpellsMap, FetchError> {
+        let spells: Vec<Spell> = self.game_data(version, "summoner-spells.json").await?;
+        Ok(spells
+            .into_iter()
+            .map(|spell| {
+                let key = spell.id.to_string();
+                let info = SummonerSpell {
+                    id: key.clone(),
+                    key: key.clone(),
+                    name: spell.name,
+                    description: spell.description,
+                    modes: spell.game_modes,
+                };
+                (key, info)
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,215 @@
+//! Every champion in the game, looked up by id, alias or localized name.
+//!
+//! Built from the champion list of the game data provider, which goes through the
+//! HTTP cache so it survives restarts and works offline, and refined with the
+//! LCU inventory, whose names follow the client's language.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use kv_log_macro::info;
+use lazy_static::lazy_static;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    api::SummonerChampion,
+    web::{self, ChampionsMap, FetchError},
+};
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Champion {
+    pub id: i64,
+    /// Data Dragon id such as `MonkeyKing`, which builds are keyed by.
+    pub alias: String,
+    pub name: String,
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct ChampionRegistry {
+    champions: Vec<Champion>,
+    by_id: HashMap<i64, usize>,
+    by_alias: HashMap<String, usize>,
+    by_name: HashMap<String, usize>,
+}
+
+impl ChampionRegistry {
+    pub fn new(champions: Vec<Champion>) -> Self {
+        let mut registry = Self::default();
+        registry.extend(champions);
+        registry
+    }
+
+    /// Reads `ChampInfo.key` as the id, skipping entries without a numeric key.
+    pub fn from_champion_list(map: &ChampionsMap) -> Self {
+        let mut champions = map
+            .values()
+            .filter_map(|c| {
+                Some(Champion {
+                    id: c.key.parse().ok()?,
+                    alias: c.id.clone(),
+                    name: c.name.clone(),
+                })
+            })
+            .collect::<Vec<_>>();
+        champions.sort_by_key(|c| c.id);
+        Self::new(champions)
+    }
+
+    /// Adds champions missing from the registry and takes over the names of known ones.
+    pub fn extend(&mut self, champions: impl IntoIterator<Item = Champion>) {
+        for champion in champions {
+            if champion.id <= 0 || champion.alias.is_empty() {
+                continue;
+            }
+            match self.by_id.get(&champion.id) {
+                Some(&idx) if !champion.name.is_empty() => {
+                    self.champions[idx].name = champion.name;
+                }
+                Some(_) => {}
+                None => {
+                    self.by_id.insert(champion.id, self.champions.len());
+                    self.champions.push(champion);
+                }
+            }
+        }
+        self.reindex();
+    }
+
+    fn reindex(&mut self) {
+        self.by_id.clear();
+        self.by_alias.clear();
+        self.by_name.clear();
+        for (idx, c) in self.champions.iter().enumerate() {
+            self.by_id.insert(c.id, idx);
+            self.by_alias.insert(c.alias.to_lowercase(), idx);
+            self.by_name.insert(c.name.to_lowercase(), idx);
+        }
+    }
+
+    pub fn by_id(&self, id: i64) -> Option<&Champion> {
+        self.by_id.get(&id).map(|&idx| &self.champions[idx])
+    }
+
+    pub fn by_alias(&self, alias: &str) -> Option<&Champion> {
+        self.by_alias
+            .get(&alias.to_lowercase())
+            .map(|&idx| &self.champions[idx])
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<&Champion> {
+        self.by_name
+            .get(&name.trim().to_lowercase())
+            .map(|&idx| &self.champions[idx])
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Champion> {
+        self.champions.iter()
+    }
+
+    pub fn random(&self) -> Option<&Champion> {
+        self.champions.choose(&mut rand::thread_rng())
+    }
+
+    pub fn len(&self) -> usize {
+        self.champions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.champions.is_empty()
+    }
+}
+
+impl From<&SummonerChampion> for Champion {
+    fn from(c: &SummonerChampion) -> Self {
+        Champion {
+            id: c.id,
+            alias: c.alias.clone(),
+            name: c.name.clone(),
+        }
+    }
+}
+
+lazy_static! {
+    static ref SHARED: RwLock<Arc<ChampionRegistry>> = RwLock::new(Default::default());
+}
+
+/// The registry as last loaded, empty until `load` has succeeded once.
+pub fn shared() -> Arc<ChampionRegistry> {
+    SHARED.read().unwrap().clone()
+}
+
+/// Loads the champion list and merges it into the shared registry.
+pub async fn load() -> Result<Arc<ChampionRegistry>, FetchError> {
+    let list = web::fetch_champion_list().await?;
+    let mut registry = ChampionRegistry::from_champion_list(&list);
+    registry.extend(shared().iter().cloned());
+
+    let registry = Arc::new(registry);
+    info!("loaded {} champions", registry.len());
+    *SHARED.write().unwrap() = registry.clone();
+    Ok(registry)
+}
+
+/// The shared registry, loading it first if that never happened.
+pub async fn get() -> Result<Arc<ChampionRegistry>, FetchError> {
+    let registry = shared();
+    if registry.is_empty() {
+        return load().await;
+    }
+    Ok(registry)
+}
+
+/// Merges the champions from the LCU inventory, picking up their localized names.
+pub fn update_from_inventory(champions: &[SummonerChampion]) {
+    let mut shared = SHARED.write().unwrap();
+    let mut registry = (**shared).clone();
+    registry.extend(champions.iter().map(Champion::from));
+    *shared = Arc::new(registry);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::web::ChampInfo;
+
+    #[test]
+    fn looks_up_by_id_alias_and_localized_name() {
+        let list = ChampionsMap::from([
+            (
+                String::from("MonkeyKing"),
+                ChampInfo {
+                    id: String::from("MonkeyKing"),
+                    key: String::from("62"),
+                    name: String::from("Wukong"),
+                    ..Default::default()
+                },
+            ),
+            (
+                String::from("Hwei"),
+                ChampInfo {
+                    id: String::from("Hwei"),
+                    key: String::from("910"),
+                    name: String::from("Hwei"),
+                    ..Default::default()
+                },
+            ),
+        ]);
+        let mut registry = ChampionRegistry::from_champion_list(&list);
+        assert_eq!(registry.len(), 2);
+        assert_eq!(registry.by_id(910).unwrap().alias, "Hwei");
+        assert_eq!(registry.by_alias("monkeyking").unwrap().id, 62);
+
+        registry.extend([Champion {
+            id: 62,
+            alias: String::from("MonkeyKing"),
+            name: String::from("齐天大圣"),
+        }]);
+        assert_eq!(registry.by_name("齐天大圣").unwrap().id, 62);
+        assert!(registry.by_name("Wukong").is_none());
+        assert_eq!(registry.len(), 2);
+        assert!(registry.random().is_some());
+    }
+}
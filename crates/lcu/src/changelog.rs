@@ -0,0 +1,388 @@
+//! What changed in the builds since they were last applied: keystones, core
+//! items and win rates per champion and position.
+//!
+//! A summary of every applied build is kept in `HISTORY_FILE`; each apply is
+//! compared with it and the differences are appended to the changelog there.
+
+use std::{collections::BTreeMap, fmt, fs, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    backup::{now_millis, utc_label},
+    builds::BuildSection,
+    cancel,
+    items::ItemCatalog,
+};
+
+/// Stored next to `.settings.toml`.
+pub const HISTORY_FILE: &str = ".build-history.json";
+
+/// The oldest changelog entries are dropped beyond this.
+pub const MAX_ENTRIES: usize = 1000;
+
+/// Win rates moving less than this many points are not reported.
+pub const WIN_RATE_THRESHOLD: f64 = 0.5;
+
+/// What matters of a build when comparing it with the next one.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AppliedBuild {
+    pub source: String,
+    pub champion: String,
+    pub position: String,
+    pub version: String,
+    /// In percent.
+    pub win_rate: Option<f64>,
+    pub pick_count: i64,
+    /// Perk id of the first rune page's keystone.
+    pub keystone: Option<i64>,
+    /// Item ids of the blocks labelled core, in order.
+    pub core_items: Vec<String>,
+}
+
+impl AppliedBuild {
+    pub fn from_section(source: &str, section: &BuildSection) -> Self {
+        let mut core_items: Vec<String> = vec![];
+        let blocks = section.item_builds.iter().flat_map(|b| b.blocks.iter());
+        for block in blocks.filter(|b| b.type_field.to_lowercase().contains("core")) {
+            for item in block.items.iter().flatten() {
+                if !core_items.contains(&item.id) {
+                    core_items.push(item.id.clone());
+                }
+            }
+        }
+
+        Self {
+            source: source.to_string(),
+            champion: section.alias.clone(),
+            position: section.position.clone(),
+            version: section.version.clone(),
+            win_rate: section.win_rate,
+            pick_count: section.pick_count,
+            keystone: section
+                .runes
+                .first()
+                .and_then(|rune| rune.selected_perk_ids.first().copied()),
+            core_items,
+        }
+    }
+
+    fn key(&self) -> String {
+        format!("{}/{}/{}", self.source, self.champion, self.position)
+    }
+}
+
+/// Summaries of the sections `source` wrote.
+pub fn summarize(source: &str, sections: &[BuildSection]) -> Vec<AppliedBuild> {
+    sections
+        .iter()
+        .map(|section| AppliedBuild::from_section(source, section))
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum BuildChange {
+    Keystone {
+        from: Option<i64>,
+        to: Option<i64>,
+    },
+    CoreItems {
+        added: Vec<String>,
+        removed: Vec<String>,
+    },
+    WinRate {
+        from: f64,
+        to: f64,
+    },
+}
+
+impl BuildChange {
+    /// Describes the change, naming items through `catalog` where it knows them.
+    pub fn describe(&self, catalog: &ItemCatalog) -> String {
+        let names = |ids: &[String]| {
+            ids.iter()
+                .map(|id| catalog.name(id).unwrap_or(id).to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let perk =
+            |id: &Option<i64>| id.map_or_else(|| String::from("none"), |id| format!("#{id}"));
+        match self {
+            BuildChange::Keystone { from, to } => {
+                format!("keystone {} → {}", perk(from), perk(to))
+            }
+            BuildChange::CoreItems { added, removed } => {
+                let mut parts = vec![];
+                if !added.is_empty() {
+                    parts.push(format!("new core items {}", names(added)));
+                }
+                if !removed.is_empty() {
+                    parts.push(format!("dropped {}", names(removed)));
+                }
+                parts.join("; ")
+            }
+            BuildChange::WinRate { from, to } => {
+                format!("win rate {from:.1}% → {to:.1}% ({:+.1})", to - from)
+            }
+        }
+    }
+}
+
+impl fmt::Display for BuildChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.describe(&ItemCatalog::default()))
+    }
+}
+
+/// The changes of one champion and position from one source.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChangelogEntry {
+    pub source: String,
+    pub champion: String,
+    pub position: String,
+    pub from_version: String,
+    pub to_version: String,
+    /// Unix timestamp in milliseconds.
+    pub applied_at: u64,
+    pub changes: Vec<BuildChange>,
+}
+
+impl ChangelogEntry {
+    /// `YYYY-MM-DD HH:MM:SS` in UTC.
+    pub fn applied_label(&self) -> String {
+        utc_label(self.applied_at)
+    }
+}
+
+/// The differences between the build applied before and the one applied now.
+pub fn diff(old: &AppliedBuild, new: &AppliedBuild) -> Vec<BuildChange> {
+    let mut changes = vec![];
+    if old.keystone != new.keystone {
+        changes.push(BuildChange::Keystone {
+            from: old.keystone,
+            to: new.keystone,
+        });
+    }
+
+    let added = new
+        .core_items
+        .iter()
+        .filter(|id| !old.core_items.contains(id))
+        .cloned()
+        .collect::<Vec<_>>();
+    let removed = old
+        .core_items
+        .iter()
+        .filter(|id| !new.core_items.contains(id))
+        .cloned()
+        .collect::<Vec<_>>();
+    if !added.is_empty() || !removed.is_empty() {
+        changes.push(BuildChange::CoreItems { added, removed });
+    }
+
+    if let (Some(from), Some(to)) = (old.win_rate, new.win_rate) {
+        if (to - from).abs() >= WIN_RATE_THRESHOLD {
+            changes.push(BuildChange::WinRate { from, to });
+        }
+    }
+    changes
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BuildHistory {
+    /// The build last applied, by source, champion and position.
+    #[serde(default)]
+    pub latest: BTreeMap<String, AppliedBuild>,
+    /// Oldest first.
+    #[serde(default)]
+    pub entries: Vec<ChangelogEntry>,
+}
+
+impl BuildHistory {
+    pub fn load(path: &Path) -> io::Result<BuildHistory> {
+        match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).map_err(|err| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: {}", path.display(), err),
+                )
+            }),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(BuildHistory::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let content = serde_json::to_string_pretty(self)?;
+        cancel::write_atomically(path, content.as_bytes())
+    }
+
+    /// Compares `applied` with the builds applied before, remembers them as
+    /// the latest and returns the new changelog entries. Builds applied for
+    /// the first time have nothing to compare with and add no entry.
+    pub fn record(&mut self, applied: &[AppliedBuild]) -> Vec<ChangelogEntry> {
+        let applied_at = now_millis();
+        let mut entries = vec![];
+        for build in applied {
+            if let Some(old) = self.latest.insert(build.key(), build.clone()) {
+                let changes = diff(&old, build);
+                if !changes.is_empty() {
+                    entries.push(ChangelogEntry {
+                        source: build.source.clone(),
+                        champion: build.champion.clone(),
+                        position: build.position.clone(),
+                        from_version: old.version,
+                        to_version: build.version.clone(),
+                        applied_at,
+                        changes,
+                    });
+                }
+            }
+        }
+
+        self.entries.extend(entries.iter().cloned());
+        let excess = self.entries.len().saturating_sub(MAX_ENTRIES);
+        self.entries.drain(..excess);
+        entries
+    }
+
+    /// The changelog, newest first.
+    pub fn newest_first(&self) -> impl Iterator<Item = &ChangelogEntry> {
+        self.entries.iter().rev()
+    }
+}
+
+/// Records `applied` in the history at `path`, see `BuildHistory::record`.
+pub fn record(path: &Path, applied: &[AppliedBuild]) -> io::Result<Vec<ChangelogEntry>> {
+    let mut history = BuildHistory::load(path)?;
+    let entries = history.record(applied);
+    history.save(path)?;
+    Ok(entries)
+}
+
+/// `entries` as a Markdown document, one section per apply.
+pub fn to_markdown(entries: &[ChangelogEntry], catalog: &ItemCatalog) -> String {
+    let mut out = String::from("# Build changelog\n");
+    let mut last_applied = None;
+    for entry in entries {
+        if last_applied != Some(entry.applied_at) {
+            last_applied = Some(entry.applied_at);
+            out.push_str(&format!("\n## {}\n\n", entry.applied_label()));
+        }
+        let versions = if entry.from_version == entry.to_version {
+            entry.to_version.clone()
+        } else {
+            format!("{} → {}", entry.from_version, entry.to_version)
+        };
+        out.push_str(&format!(
+            "- **{} {}** ({}, {})\n",
+            entry.champion, entry.position, entry.source, versions
+        ));
+        for change in entry.changes.iter() {
+            out.push_str(&format!("  - {}\n", change.describe(catalog)));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        api::GameItem,
+        builds::{Block, Item, ItemBuild, Rune},
+    };
+
+    fn section(version: &str, win_rate: f64, keystone: i64, core: &[&str]) -> BuildSection {
+        BuildSection {
+            alias: String::from("Ahri"),
+            position: String::from("mid"),
+            version: version.to_string(),
+            win_rate: Some(win_rate),
+            item_builds: vec![ItemBuild {
+                blocks: vec![Block {
+                    type_field: String::from("Core Items"),
+                    items: Some(
+                        core.iter()
+                            .map(|id| Item {
+                                id: id.to_string(),
+                                count: 1,
+                            })
+                            .collect(),
+                    ),
+                }],
+                ..Default::default()
+            }],
+            runes: vec![Rune {
+                selected_perk_ids: vec![keystone, 8126],
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn records_changes_since_the_last_apply() {
+        let mut history = BuildHistory::default();
+        let first = history.record(&summarize(
+            "op.gg",
+            &[section("14.3", 51.2, 8112, &["6655", "3020"])],
+        ));
+        assert!(first.is_empty());
+
+        let unchanged = history.record(&summarize(
+            "op.gg",
+            &[section("14.3", 51.4, 8112, &["3020", "6655"])],
+        ));
+        assert!(unchanged.is_empty());
+
+        let entries = history.record(&summarize(
+            "op.gg",
+            &[section("14.4", 49.9, 8229, &["6655", "4645"])],
+        ));
+        assert_eq!(entries.len(), 1);
+        assert_eq!(
+            entries[0]
+                .changes
+                .iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>(),
+            [
+                "keystone #8112 → #8229",
+                "new core items 4645; dropped 3020",
+                "win rate 51.4% → 49.9% (-1.5)",
+            ]
+        );
+        assert_eq!(history.entries.len(), 1);
+
+        let catalog = ItemCatalog::from_game_items(&[GameItem {
+            id: 4645,
+            name: String::from("Shadowflame"),
+        }]);
+        let markdown = to_markdown(&entries, &catalog);
+        assert!(markdown.contains("- **Ahri mid** (op.gg, 14.3 → 14.4)\n"));
+        assert!(markdown.contains("  - new core items Shadowflame; dropped 3020\n"));
+    }
+
+    #[test]
+    fn reports_win_rates_given_as_fractions_in_percent() {
+        // as a source gives them, a fraction rather than a percentage
+        let read = |version: &str, win_rate: f64| {
+            let mut value = serde_json::to_value(section(version, 0., 8112, &["6655"])).unwrap();
+            value["winRate"] = win_rate.into();
+            serde_json::from_value::<BuildSection>(value).unwrap()
+        };
+        let mut history = BuildHistory::default();
+        history.record(&summarize("u.gg", &[read("14.3", 0.512)]));
+        let entries = history.record(&summarize("u.gg", &[read("14.4", 0.49)]));
+        assert_eq!(entries.len(), 1);
+        assert_eq!(
+            entries[0].changes[0].to_string(),
+            "win rate 51.2% → 49.0% (-2.2)"
+        );
+    }
+}
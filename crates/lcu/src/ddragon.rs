@@ -0,0 +1,386 @@
+//! Static game data straight from Riot's Data Dragon, as an alternative to
+//! the `/api/data-dragon/*` endpoints of the build service. Community Dragon
+//! stands in for files Data Dragon fails to serve.
+
+use std::{collections::HashMap, env, sync::RwLock};
+
+use futures::future::try_join4;
+use kv_log_macro::{info, warn};
+use lazy_static::lazy_static;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    cache::{self, CachePolicy, HttpCache, GAME_DATA_POLICY, VERSIONS_POLICY},
+    cdragon::{self, CommunityDragon},
+    endpoints::{self, Mirrors},
+    fetch_error::FetchError,
+    web::{ChampionsMap, DataDragonRune},
+};
+
+pub const DDRAGON_URL_ENV: &str = "CHAMPR_DDRAGON_URL";
+pub const DEFAULT_DDRAGON_URLS: &[&str] = &["https://ddragon.leagueoflegends.com"];
+pub const DEFAULT_LOCALE: &str = "en_US";
+
+/// Prefix of the http cache keys, the paths are also used by the service.
+const CACHE_KEY_PREFIX: &str = "ddragon:";
+
+/// Where champion lists, rune trees and other static data come from.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GameDataProvider {
+    /// The `c.lbj.moe` compatible build service.
+    #[default]
+    Service,
+    DataDragon,
+}
+
+lazy_static! {
+    static ref DDRAGON: Mirrors = Mirrors::new(DEFAULT_DDRAGON_URLS);
+    static ref SETTINGS: RwLock<(GameDataProvider, String)> =
+        RwLock::new((GameDataProvider::Service, DEFAULT_LOCALE.to_string()));
+}
+
+/// Chooses the provider and the Data Dragon locale, e.g. `zh_CN`.
+/// `CHAMPR_DDRAGON_URL` replaces the Data Dragon host, `CHAMPR_CDRAGON_URL`
+/// the Community Dragon one.
+pub fn configure(provider: GameDataProvider, locale: &str) {
+    let locale = if locale.trim().is_empty() {
+        DEFAULT_LOCALE
+    } else {
+        locale.trim()
+    };
+    *SETTINGS.write().unwrap() = (provider, locale.to_string());
+
+    let from_env = env::var(DDRAGON_URL_ENV)
+        .map(|v| endpoints::parse_list(&v))
+        .unwrap_or_default();
+    if !from_env.is_empty() {
+        DDRAGON.set(from_env);
+    }
+    cdragon::configure();
+
+    info!("game data from {:?}, locale {}", provider, locale);
+}
+
+pub fn provider() -> GameDataProvider {
+    SETTINGS.read().unwrap().0
+}
+
+pub fn locale() -> String {
+    SETTINGS.read().unwrap().1.clone()
+}
+
+/// The configured Data Dragon with the Community Dragon fallback, going
+/// through the shared http cache.
+pub fn shared() -> DataDragon<'static> {
+    DataDragon::new(&DDRAGON, cache::shared().clone(), &locale()).with_fallback(cdragon::mirrors())
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ItemGold {
+    pub base: i64,
+    pub total: i64,
+    pub sell: i64,
+    pub purchasable: bool,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ItemInfo {
+    pub name: String,
+    pub plaintext: String,
+    pub gold: ItemGold,
+    pub tags: Vec<String>,
+    /// Map id to whether the item can be bought there.
+    pub maps: HashMap<String, bool>,
+    pub into: Vec<String>,
+    pub from: Vec<String>,
+}
+
+pub type ItemsMap = HashMap<String, ItemInfo>;
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SummonerSpell {
+    pub id: String,
+    pub key: String,
+    pub name: String,
+    pub description: String,
+    pub modes: Vec<String>,
+}
+
+pub type SummonerSpellsMap = HashMap<String, SummonerSpell>;
+
+/// The `{ "type", "version", "data": { ... } }` envelope of most Data Dragon files.
+#[derive(Deserialize)]
+struct DataFile<T> {
+    data: HashMap<String, T>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct GameData {
+    pub version: String,
+    pub champions: ChampionsMap,
+    pub runes: Vec<DataDragonRune>,
+    pub items: ItemsMap,
+    pub summoner_spells: SummonerSpellsMap,
+}
+
+pub struct DataDragon<'a> {
+    mirrors: &'a Mirrors,
+    fallback: Option<&'a Mirrors>,
+    cache: HttpCache,
+    locale: String,
+}
+
+impl<'a> DataDragon<'a> {
+    pub fn new(mirrors: &'a Mirrors, cache: HttpCache, locale: &str) -> Self {
+        Self {
+            mirrors,
+            fallback: None,
+            cache,
+            locale: locale.to_string(),
+        }
+    }
+
+    /// Asks the Community Dragon `mirrors` whenever Data Dragon fails.
+    pub fn with_fallback(mut self, mirrors: &'a Mirrors) -> Self {
+        self.fallback = Some(mirrors);
+        self
+    }
+
+    /// Community Dragon in place of the file that failed with `err`, if there
+    /// is a fallback.
+    fn community(&self, file: &str, err: &FetchError) -> Option<CommunityDragon<'_>> {
+        let mirrors = self.fallback?;
+        warn!("Data Dragon {}: {}, trying Community Dragon", file, err);
+        Some(CommunityDragon::new(mirrors, &self.cache, &self.locale))
+    }
+
+    async fn get<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        policy: CachePolicy,
+    ) -> Result<T, FetchError> {
+        let key = format!("{CACHE_KEY_PREFIX}{path}");
+        self.mirrors
+            .get_cached_json_in(&self.cache, &key, path, policy)
+            .await
+    }
+
+    /// The current patch, i.e. the first entry of `versions.json`.
+    pub async fn latest_version(&self) -> Result<String, FetchError> {
+        let result = self
+            .get::<Vec<String>>("/api/versions.json", VERSIONS_POLICY)
+            .await
+            .and_then(|versions| {
+                versions
+                    .into_iter()
+                    .next()
+                    .ok_or_else(|| FetchError::NotFound(String::from("Data Dragon versions")))
+            });
+        match result {
+            Err(err) => match self.community("versions.json", &err) {
+                Some(cdragon) => cdragon.latest_version().await,
+                None => Err(err),
+            },
+            version => version,
+        }
+    }
+
+    async fn data_file<T: DeserializeOwned>(
+        &self,
+        version: &str,
+        file: &str,
+    ) -> Result<T, FetchError> {
+        let path = format!("/cdn/{version}/data/{}/{file}", &self.locale);
+        self.get(&path, GAME_DATA_POLICY).await
+    }
+
+    pub async fn champions(&self, version: &str) -> Result<ChampionsMap, FetchError> {
+        match self
+            .data_file::<DataFile<_>>(version, "champion.json")
+            .await
+        {
+            Ok(file) => Ok(file.data),
+            Err(err) => match self.community("champion.json", &err) {
+                Some(cdragon) => cdragon.champions(version).await,
+                None => Err(err),
+            },
+        }
+    }
+
+    pub async fn runes(&self, version: &str) -> Result<Vec<DataDragonRune>, FetchError> {
+        match self.data_file(version, "runesReforged.json").await {
+            Ok(runes) => Ok(runes),
+            Err(err) => match self.community("runesReforged.json", &err) {
+                Some(cdragon) => cdragon.runes(version).await,
+                None => Err(err),
+            },
+        }
+    }
+
+    pub async fn items(&self, version: &str) -> Result<ItemsMap, FetchError> {
+        match self.data_file::<DataFile<_>>(version, "item.json").await {
+            Ok(file) => Ok(file.data),
+            Err(err) => match self.community("item.json", &err) {
+                Some(cdragon) => cdragon.items(version).await,
+                None => Err(err),
+            },
+        }
+    }
+
+    pub async fn summoner_spells(&self, version: &str) -> Result<SummonerSpellsMap, FetchError> {
+        match self
+            .data_file::<DataFile<_>>(version, "summoner.json")
+            .await
+        {
+            Ok(file) => Ok(file.data),
+            Err(err) => match self.community("summoner.json", &err) {
+                Some(cdragon) => cdragon.summoner_spells(version).await,
+                None => Err(err),
+            },
+        }
+    }
+
+    /// Loads everything for the latest patch.
+    pub async fn load(&self) -> Result<GameData, FetchError> {
+        let version = self.latest_version().await?;
+        let (champions, runes, items, summoner_spells) = try_join4(
+            self.champions(&version),
+            self.runes(&version),
+            self.items(&version),
+            self.summoner_spells(&version),
+        )
+        .await?;
+
+        Ok(GameData {
+            version,
+            champions,
+            runes,
+            items,
+            summoner_spells,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{self, Response};
+
+    fn static_file(path: &str) -> Response {
+        let body = match path {
+            "/api/versions.json" => r#"["14.2.1", "14.1.1"]"#,
+            "/cdn/14.2.1/data/zh_CN/champion.json" => {
+                r#"{"type": "champion", "version": "14.2.1", "data": {"Ahri": {
+                    "version": "14.2.1", "id": "Ahri", "key": "103", "name": "阿狸",
+                    "title": "九尾妖狐", "blurb": "...", "partype": "法力",
+                    "image": {"full": "Ahri.png", "sprite": "champion0.png", "group": "champion",
+                              "x": 48, "y": 0, "w": 48, "h": 48},
+                    "tags": ["Mage", "Assassin"]}}}"#
+            }
+            "/cdn/14.2.1/data/zh_CN/runesReforged.json" => {
+                r#"[{"id": 8100, "key": "Domination", "icon": "perk-images/Styles/7200_Domination.png",
+                     "name": "主宰", "slots": [{"runes": [{"id": 8112, "key": "Electrocute",
+                     "icon": "perk-images/Styles/Domination/Electrocute/Electrocute.png",
+                     "name": "电刑", "shortDesc": "short", "longDesc": "long"}]}]}]"#
+            }
+            "/cdn/14.2.1/data/zh_CN/item.json" => {
+                r#"{"type": "item", "version": "14.2.1", "basic": {}, "data": {"1001": {
+                    "name": "鞋子", "plaintext": "略微提升移动速度", "into": ["3006"],
+                    "gold": {"base": 300, "purchasable": true, "total": 300, "sell": 210},
+                    "tags": ["Boots"], "maps": {"11": true, "12": true}}}}"#
+            }
+            "/cdn/14.2.1/data/zh_CN/summoner.json" => {
+                r#"{"type": "summoner", "version": "14.2.1", "data": {"SummonerFlash": {
+                    "id": "SummonerFlash", "name": "闪现", "description": "...", "key": "4",
+                    "modes": ["CLASSIC", "ARAM"]}}}"#
+            }
+            _ => return Response::status(404),
+        };
+        Response::ok(body)
+    }
+
+    #[tokio::test]
+    async fn loads_latest_patch_for_locale() {
+        let server = test_server::serve(|req| static_file(&req.path)).await;
+        let mirrors = Mirrors::new(&[server.url("")]);
+        let cache = HttpCache::new(test_server::temp_dir("ddragon"));
+
+        let data = DataDragon::new(&mirrors, cache, "zh_CN")
+            .load()
+            .await
+            .unwrap();
+
+        assert_eq!(data.version, "14.2.1");
+        let ahri = &data.champions["Ahri"];
+        assert_eq!((ahri.key.as_str(), ahri.name.as_str()), ("103", "阿狸"));
+        assert_eq!(ahri.image.full, "Ahri.png");
+        assert_eq!(data.runes[0].slots[0].runes[0].id, 8112);
+        assert_eq!(data.items["1001"].gold.total, 300);
+        assert!(data.items["1001"].maps["11"]);
+        assert_eq!(data.summoner_spells["SummonerFlash"].key, "4");
+    }
+
+    fn community_file(path: &str) -> Response {
+        let dir = "/14.2/plugins/rcp-be-lol-game-data/global/zh_cn/v1";
+        let body = match path.strip_prefix(dir) {
+            Some("/champion-summary.json") => {
+                r#"[{"id": -1, "name": "无", "alias": "None", "roles": []},
+                    {"id": 103, "name": "阿狸", "alias": "Ahri", "roles": ["mage", "assassin"]}]"#
+            }
+            Some("/perks.json") => {
+                r#"[{"id": 8112, "name": "电刑", "shortDesc": "short", "longDesc": "long",
+                     "iconPath": "/lol-game-data/assets/v1/perk-images/Styles/Domination/Electrocute/Electrocute.png"},
+                    {"id": 5008, "name": "适应之力", "iconPath": "/lol-game-data/assets/v1/perk-images/StatMods/StatModsAdaptiveForceIcon.png"}]"#
+            }
+            Some("/perkstyles.json") => {
+                r#"{"schemaVersion": 2, "styles": [{"id": 8100, "name": "主宰",
+                    "iconPath": "/lol-game-data/assets/v1/perk-images/Styles/7200_Domination.png",
+                    "slots": [{"type": "kKeyStone", "perks": [8112]},
+                              {"type": "kStatMod", "perks": [5008]}]}]}"#
+            }
+            Some("/items.json") => {
+                r#"[{"id": 1001, "name": "鞋子", "inStore": true, "from": [], "to": [3006],
+                     "categories": ["Boots"], "price": 300, "priceTotal": 300}]"#
+            }
+            Some("/summoner-spells.json") => {
+                r#"[{"id": 4, "name": "闪现", "description": "...", "gameModes": ["CLASSIC"]}]"#
+            }
+            _ => return Response::status(404),
+        };
+        Response::ok(body)
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_community_dragon() {
+        let ddragon = test_server::serve(|req| match req.path.as_str() {
+            "/api/versions.json" => static_file(&req.path),
+            _ => Response::status(404),
+        })
+        .await;
+        let cdragon = test_server::serve(|req| community_file(&req.path)).await;
+        let mirrors = Mirrors::new(&[ddragon.url("")]);
+        let fallback = Mirrors::new(&[cdragon.url("")]);
+        let cache = HttpCache::new(test_server::temp_dir("cdragon"));
+
+        let data = DataDragon::new(&mirrors, cache, "zh_CN")
+            .with_fallback(&fallback)
+            .load()
+            .await
+            .unwrap();
+
+        assert_eq!(data.version, "14.2.1");
+        assert_eq!(data.champions.len(), 1);
+        let ahri = &data.champions["Ahri"];
+        assert_eq!((ahri.key.as_str(), ahri.name.as_str()), ("103", "阿狸"));
+        assert_eq!(ahri.tags, ["Mage", "Assassin"]);
+        assert_eq!(data.runes[0].key, "Domination");
+        assert_eq!(data.runes[0].icon, "perk-images/Styles/7200_Domination.png");
+        assert_eq!(data.runes[0].slots.len(), 1);
+        assert_eq!(data.runes[0].slots[0].runes[0].key, "Electrocute");
+        assert_eq!(data.items["1001"].into, ["3006"]);
+        assert_eq!(data.summoner_spells["4"].name, "闪现");
+    }
+}
@@ -0,0 +1,271 @@
+use std::{
+    env,
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+
+use futures::future::join_all;
+use kv_log_macro::{info, warn};
+use lazy_static::lazy_static;
+use serde::de::DeserializeOwned;
+
+use crate::{
+    cache::{self, CachePolicy, HttpCache},
+    fetch_error::FetchError,
+};
+
+pub const SERVICE_URL_ENV: &str = "CHAMPR_SERVICE_URL";
+pub const NPM_REGISTRY_ENV: &str = "CHAMPR_NPM_REGISTRY";
+
+pub const DEFAULT_SERVICE_URLS: &[&str] = &["https://c.lbj.moe"];
+pub const DEFAULT_NPM_REGISTRIES: &[&str] = &[
+    "https://registry.npmjs.org",
+    "https://registry.npmmirror.com",
+    "https://mirrors.cloud.tencent.com/npm",
+];
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+lazy_static! {
+    static ref SERVICE: Mirrors = Mirrors::new(DEFAULT_SERVICE_URLS);
+    static ref NPM: Mirrors = Mirrors::new(DEFAULT_NPM_REGISTRIES);
+    static ref PROBE_CLIENT: reqwest::Client = reqwest::Client::builder()
+        .timeout(PROBE_TIMEOUT)
+        .build()
+        .unwrap();
+}
+
+/// The `c.lbj.moe` compatible services, preferred one first.
+pub fn service() -> &'static Mirrors {
+    &SERVICE
+}
+
+/// The npm registries hosting the `@champ-r/*` packages, preferred one first.
+pub fn npm() -> &'static Mirrors {
+    &NPM
+}
+
+/// Applies the configured mirror lists. The environment wins over the config,
+/// and an empty list falls back to the built-in defaults.
+pub fn configure(service_urls: &[String], npm_registries: &[String]) {
+    SERVICE.set(resolve(SERVICE_URL_ENV, service_urls, DEFAULT_SERVICE_URLS));
+    NPM.set(resolve(
+        NPM_REGISTRY_ENV,
+        npm_registries,
+        DEFAULT_NPM_REGISTRIES,
+    ));
+
+    info!(
+        "service mirrors: {:?}, npm mirrors: {:?}",
+        SERVICE.list(),
+        NPM.list()
+    );
+}
+
+fn resolve(env_key: &str, configured: &[String], defaults: &[&str]) -> Vec<String> {
+    let from_env = env::var(env_key)
+        .map(|v| parse_list(&v))
+        .unwrap_or_default();
+    if !from_env.is_empty() {
+        return from_env;
+    }
+
+    let configured = configured
+        .iter()
+        .map(|u| normalize(u))
+        .filter(|u| !u.is_empty())
+        .collect::<Vec<_>>();
+    if !configured.is_empty() {
+        return configured;
+    }
+
+    defaults.iter().map(|u| u.to_string()).collect()
+}
+
+/// Splits a comma separated list of base urls, e.g. from an environment variable.
+pub fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(normalize)
+        .filter(|u| !u.is_empty())
+        .collect()
+}
+
+fn normalize(url: &str) -> String {
+    url.trim().trim_end_matches('/').to_string()
+}
+
+/// Probes both mirror lists and moves the fastest reachable ones to the front.
+pub async fn rank_all() {
+    futures::join!(SERVICE.rank_by_latency(), NPM.rank_by_latency());
+}
+
+#[derive(Debug, Default)]
+pub struct Mirrors {
+    urls: RwLock<Vec<String>>,
+}
+
+impl Mirrors {
+    pub fn new<S: AsRef<str>>(urls: &[S]) -> Self {
+        Self {
+            urls: RwLock::new(urls.iter().map(|u| normalize(u.as_ref())).collect()),
+        }
+    }
+
+    pub fn set(&self, urls: Vec<String>) {
+        *self.urls.write().unwrap() = urls;
+    }
+
+    pub fn list(&self) -> Vec<String> {
+        self.urls.read().unwrap().clone()
+    }
+
+    pub fn primary(&self) -> String {
+        self.list().first().cloned().unwrap_or_default()
+    }
+
+    /// Moves the mirror that `url` was served from to the front, so it is tried first next time.
+    pub fn promote(&self, url: &str) {
+        let mut urls = self.urls.write().unwrap();
+        if let Some(idx) = urls.iter().position(|base| url.starts_with(base.as_str())) {
+            if idx > 0 {
+                let base = urls.remove(idx);
+                info!("switching to mirror {}", &base);
+                urls.insert(0, base);
+            }
+        }
+    }
+
+    /// Calls `f` with each base url in order until one succeeds. Fails with
+    /// `FetchError::NotFound` when no mirror is configured.
+    pub async fn failover<T, E, F, Fut>(&self, f: F) -> Result<T, E>
+    where
+        E: std::fmt::Debug + From<FetchError>,
+        F: Fn(String) -> Fut,
+        Fut: std::future::Future<Output = Result<T, E>>,
+    {
+        let urls = self.list();
+        let mut last_err = None;
+        for base in urls.iter() {
+            match f(base.clone()).await {
+                Ok(v) => {
+                    self.promote(base);
+                    return Ok(v);
+                }
+                Err(err) => {
+                    warn!("mirror {} failed: {:?}", base, err);
+                    last_err = Some(err);
+                }
+            }
+        }
+
+        Err(last_err
+            .unwrap_or_else(|| FetchError::NotFound(String::from("any configured mirror")).into()))
+    }
+
+    /// Fetches `path` through the http cache, failing over between mirrors.
+    pub async fn get_cached_json<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        policy: CachePolicy,
+    ) -> Result<T, FetchError> {
+        self.get_cached_json_in(cache::shared(), path, path, policy)
+            .await
+    }
+
+    /// Like `get_cached_json`, but with a cache other than the shared one and
+    /// the entry stored under `key`, so that hosts sharing a cache keep apart.
+    pub async fn get_cached_json_in<T: DeserializeOwned>(
+        &self,
+        cache: &HttpCache,
+        key: &str,
+        path: &str,
+        policy: CachePolicy,
+    ) -> Result<T, FetchError> {
+        let urls = self
+            .list()
+            .iter()
+            .map(|base| format!("{base}{path}"))
+            .collect::<Vec<_>>();
+        let entry = cache.get(key, &urls, policy).await?;
+        self.promote(&entry.url);
+        entry.decode()
+    }
+
+    /// Reorders the mirrors by how fast they answer. Unreachable ones keep their
+    /// relative order at the end.
+    pub async fn rank_by_latency(&self) {
+        let urls = self.list();
+        if urls.len() < 2 || cache::is_offline() {
+            return;
+        }
+
+        let probes = urls.iter().map(|base| async move {
+            let started = Instant::now();
+            match PROBE_CLIENT.head(base).send().await {
+                Ok(_) => Some(started.elapsed()),
+                Err(err) => {
+                    warn!("mirror {} unreachable: {:?}", base, err);
+                    None
+                }
+            }
+        });
+        let latencies = join_all(probes).await;
+
+        let mut ranked = urls.into_iter().zip(latencies).collect::<Vec<_>>();
+        ranked.sort_by_key(|(_, latency)| latency.unwrap_or(Duration::MAX));
+        info!("mirrors ranked by latency: {:?}", &ranked);
+
+        self.set(ranked.into_iter().map(|(url, _)| url).collect());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{self, Response};
+
+    #[tokio::test]
+    async fn fails_over_and_ranks_reachable_mirrors_first() {
+        let server = test_server::serve(|_| Response::ok("{}")).await;
+        let down = "http://127.0.0.1:1".to_string();
+        let up = server.url("");
+        let mirrors = Mirrors::new(&[down.clone(), up.clone()]);
+
+        let served_by = mirrors
+            .failover(|base| async move {
+                PROBE_CLIENT
+                    .get(format!("{base}/ping"))
+                    .send()
+                    .await
+                    .map(|_| base)
+                    .map_err(FetchError::from)
+            })
+            .await
+            .unwrap();
+        assert_eq!(served_by, up);
+        assert_eq!(mirrors.list(), vec![up.clone(), down.clone()]);
+
+        let mirrors = Mirrors::new(&[down.clone(), up.clone()]);
+        mirrors.rank_by_latency().await;
+        assert_eq!(mirrors.primary(), up);
+
+        let none = Mirrors::new::<String>(&[]);
+        let result = none
+            .failover(|base| async move { Ok::<_, FetchError>(base) })
+            .await;
+        assert!(matches!(result, Err(FetchError::NotFound(_))));
+    }
+
+    #[test]
+    fn parses_mirror_lists() {
+        assert_eq!(
+            parse_list(" https://a.example/ ,,https://b.example"),
+            vec!["https://a.example", "https://b.example"]
+        );
+        assert_eq!(
+            resolve("CHAMPR_TEST_UNSET", &[], DEFAULT_SERVICE_URLS),
+            vec!["https://c.lbj.moe"]
+        );
+    }
+}
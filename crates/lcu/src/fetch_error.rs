@@ -0,0 +1,244 @@
+use std::{error::Error, fmt, sync::Arc};
+
+use serde::de::DeserializeOwned;
+
+const MAX_BODY_LEN: usize = 200;
+
+/// Errors talking to the build service, npm mirrors, GitHub and other remote hosts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FetchError {
+    Timeout {
+        url: String,
+    },
+    ConnectionRefused {
+        url: String,
+    },
+    Status {
+        url: String,
+        status: u16,
+        body: String,
+    },
+    Decode {
+        url: String,
+        path: String,
+        message: String,
+        source: Option<Cause>,
+    },
+    /// The remote does not know the champion or source that was asked for.
+    NotFound(String),
+    /// Offline mode is on and nothing has been cached for the resource yet.
+    Offline(String),
+    Io(String),
+    /// A download did not match the checksum its registry published.
+    Integrity {
+        url: String,
+        message: String,
+    },
+    Request {
+        url: String,
+        source: Cause,
+    },
+}
+
+/// The HTTP client error a [`FetchError`] came from, kept as its `source` so
+/// anyhow chains show it. Shared to keep `FetchError` cheap to clone.
+#[derive(Debug, Clone)]
+pub struct Cause(Arc<reqwest::Error>);
+
+impl Cause {
+    pub fn new(err: reqwest::Error) -> Self {
+        Self(Arc::new(err))
+    }
+}
+
+impl PartialEq for Cause {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.to_string() == other.0.to_string()
+    }
+}
+
+impl Eq for Cause {}
+
+impl FetchError {
+    pub fn status(url: &str, status: u16, body: &str) -> Self {
+        if status == 404 {
+            return FetchError::NotFound(url.to_string());
+        }
+
+        let mut body = body.trim().to_string();
+        if body.len() > MAX_BODY_LEN {
+            let mut end = MAX_BODY_LEN;
+            while !body.is_char_boundary(end) {
+                end -= 1;
+            }
+            body.truncate(end);
+            body.push('…');
+        }
+        FetchError::Status {
+            url: url.to_string(),
+            status,
+            body,
+        }
+    }
+
+    /// Whether trying again later, or on another mirror, might help.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            FetchError::Timeout { .. }
+            | FetchError::ConnectionRefused { .. }
+            | FetchError::Request { .. } => true,
+            FetchError::Status { status, .. } => *status >= 500 || *status == 429,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FetchError::Timeout { url } => write!(
+                f,
+                "{url} timed out. Check your connection or add a faster mirror in the settings."
+            ),
+            FetchError::ConnectionRefused { url } => write!(
+                f,
+                "Could not connect to {url}. The server may be down or blocked on your network."
+            ),
+            FetchError::Status { url, status, body } => {
+                write!(f, "{url} responded with HTTP {status}")?;
+                if !body.is_empty() {
+                    write!(f, ": {body}")?;
+                }
+                Ok(())
+            }
+            FetchError::Decode {
+                url, path, message, ..
+            } => write!(
+                f,
+                "Unexpected data from {url} at `{path}`: {message}. Updating ChampR may help."
+            ),
+            FetchError::NotFound(what) => write!(f, "Nothing found at {what}."),
+            FetchError::Offline(what) => write!(
+                f,
+                "Offline mode is on and {what} has not been downloaded yet. Turn off offline mode to fetch it."
+            ),
+            FetchError::Io(message) => write!(f, "File system error: {message}"),
+            FetchError::Integrity { url, message } => write!(
+                f,
+                "{url} failed the integrity check ({message}). The download may be corrupted or tampered with, try again or switch to another npm mirror."
+            ),
+            FetchError::Request { url, .. } => write!(f, "Request to {url} failed"),
+        }
+    }
+}
+
+impl Error for FetchError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            FetchError::Request { source, .. }
+            | FetchError::Decode {
+                source: Some(source),
+                ..
+            } => Some(source.0.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for FetchError {
+    fn from(err: reqwest::Error) -> FetchError {
+        let url = err.url().map(redact_url).unwrap_or_default();
+        if err.is_timeout() {
+            FetchError::Timeout { url }
+        } else if err.is_connect() {
+            FetchError::ConnectionRefused { url }
+        } else if let Some(status) = err.status() {
+            FetchError::status(&url, status.as_u16(), "")
+        } else if err.is_decode() {
+            FetchError::Decode {
+                url,
+                path: String::from("."),
+                message: String::from("the response body could not be read"),
+                source: Some(Cause::new(err)),
+            }
+        } else {
+            FetchError::Request {
+                url,
+                source: Cause::new(err),
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for FetchError {
+    fn from(err: std::io::Error) -> FetchError {
+        FetchError::Io(err.to_string())
+    }
+}
+
+/// Drops credentials such as the LCU remoting token, so urls are safe to show.
+pub fn redact_url(url: &reqwest::Url) -> String {
+    let mut url = url.clone();
+    let _ = url.set_username("");
+    let _ = url.set_password(None);
+    url.to_string()
+}
+
+/// Parses `body` as JSON, keeping the path to the offending field on failure.
+pub fn decode_json<T: DeserializeOwned>(url: &str, body: &str) -> Result<T, FetchError> {
+    let de = &mut serde_json::Deserializer::from_str(body);
+    serde_path_to_error::deserialize(de).map_err(|err| FetchError::Decode {
+        url: url.to_string(),
+        path: err.path().to_string(),
+        message: err.inner().to_string(),
+        source: None,
+    })
+}
+
+/// Reads a response, turning non-success statuses into [`FetchError::Status`].
+pub async fn read_text(resp: reqwest::Response) -> Result<String, FetchError> {
+    let url = redact_url(resp.url());
+    let status = resp.status();
+    let body = resp.text().await?;
+    if !status.is_success() {
+        return Err(FetchError::status(&url, status.as_u16(), &body));
+    }
+    Ok(body)
+}
+
+/// Reads and decodes a JSON response.
+pub async fn read_json<T: DeserializeOwned>(resp: reqwest::Response) -> Result<T, FetchError> {
+    let url = redact_url(resp.url());
+    let body = read_text(resp).await?;
+    decode_json(&url, &body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, serde::Deserialize)]
+    #[allow(dead_code)]
+    struct Pkg {
+        dist: Dist,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    #[allow(dead_code)]
+    struct Dist {
+        tarball: String,
+    }
+
+    #[test]
+    fn decode_error_keeps_path() {
+        let err = decode_json::<Pkg>("u", r#"{"dist":{"tarball":1}}"#).unwrap_err();
+        match err {
+            FetchError::Decode { path, .. } => assert_eq!(path, "dist.tarball"),
+            other => panic!("unexpected {other:?}"),
+        }
+        assert_eq!(
+            FetchError::status("u", 404, ""),
+            FetchError::NotFound(String::from("u"))
+        );
+    }
+}
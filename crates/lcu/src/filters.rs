@@ -0,0 +1,129 @@
+//! Quality thresholds for builds, so low-sample builds and long lists of item
+//! sets neither reach the shop nor the rune list.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::builds::BuildSection;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BuildFilter {
+    /// Builds picked fewer times are skipped.
+    pub min_pick_count: u64,
+    /// In percent. Builds without a win rate are kept.
+    pub min_win_rate: f64,
+    /// Item sets written per champion and position, 0 for no limit.
+    pub max_item_sets: usize,
+    /// Rune pages shown per champion, 0 for no limit.
+    pub max_rune_pages: usize,
+}
+
+impl BuildFilter {
+    fn passes(&self, pick_count: u64, win_rate: Option<f64>) -> bool {
+        pick_count >= self.min_pick_count && win_rate.is_none_or(|rate| rate >= self.min_win_rate)
+    }
+
+    /// Drops the sections and rune pages below the thresholds, then the item
+    /// sets beyond `max_item_sets` for each position.
+    pub fn filter_sections(&self, sections: &mut Vec<BuildSection>) {
+        sections.retain(|s| self.passes(s.pick_count.max(0) as u64, s.win_rate));
+        for section in sections.iter_mut() {
+            section
+                .runes
+                .retain(|r| self.passes(r.pick_count, r.win_rate));
+        }
+
+        if self.max_item_sets > 0 {
+            let mut written: HashMap<String, usize> = HashMap::new();
+            for section in sections.iter_mut() {
+                let count = written.entry(section.position.clone()).or_default();
+                let keep = self.max_item_sets.saturating_sub(*count);
+                section.item_builds.truncate(keep);
+                *count += section.item_builds.len();
+            }
+        }
+    }
+
+    /// Drops the rune pages below the thresholds and those beyond `max_rune_pages`.
+    pub fn filter_runes(&self, sections: &mut [BuildSection]) {
+        let mut shown = 0;
+        for section in sections.iter_mut() {
+            section.runes.retain(|r| {
+                let keep = self.passes(r.pick_count, r.win_rate)
+                    && (self.max_rune_pages == 0 || shown < self.max_rune_pages);
+                if keep {
+                    shown += 1;
+                }
+                keep
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builds::{ItemBuild, Rune};
+
+    fn section(
+        position: &str,
+        pick_count: i64,
+        win_rate: Option<f64>,
+        sets: usize,
+    ) -> BuildSection {
+        BuildSection {
+            position: position.to_string(),
+            pick_count,
+            win_rate,
+            item_builds: vec![ItemBuild::default(); sets],
+            runes: vec![
+                Rune {
+                    pick_count: 500,
+                    win_rate: Some(53.),
+                    ..Default::default()
+                },
+                Rune {
+                    pick_count: 5,
+                    win_rate: Some(60.),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn applies_thresholds_and_caps() {
+        let filter = BuildFilter {
+            min_pick_count: 100,
+            min_win_rate: 50.,
+            max_item_sets: 3,
+            max_rune_pages: 1,
+        };
+        let mut sections = vec![
+            section("mid", 1200, Some(51.), 2),
+            section("mid", 900, None, 2),
+            section("mid", 40, Some(55.), 2),
+            section("top", 300, Some(49.9), 2),
+            section("support", 300, Some(50.), 4),
+        ];
+
+        filter.filter_sections(&mut sections);
+        assert_eq!(
+            sections
+                .iter()
+                .map(|s| (s.position.as_str(), s.item_builds.len()))
+                .collect::<Vec<_>>(),
+            [("mid", 2), ("mid", 1), ("support", 3)]
+        );
+        assert!(sections.iter().all(|s| s.runes.len() == 1));
+
+        filter.filter_runes(&mut sections);
+        assert_eq!(
+            sections.iter().map(|s| s.runes.len()).collect::<Vec<_>>(),
+            [1, 0, 0]
+        );
+    }
+}
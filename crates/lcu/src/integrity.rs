@@ -0,0 +1,216 @@
+use std::fmt;
+
+use base64::{engine::general_purpose, Engine as _};
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+
+use crate::{fetch_error::FetchError, web::Dist};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Algorithm {
+    Sha1,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Algorithm::Sha1 => "sha1",
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Sha384 => "sha384",
+            Algorithm::Sha512 => "sha512",
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checksum {
+    pub algorithm: Algorithm,
+    pub digest: Vec<u8>,
+}
+
+impl Checksum {
+    /// Parses a Subresource Integrity string such as `sha512-<base64>`.
+    /// When several hashes are listed, the strongest supported one wins.
+    pub fn parse_sri(sri: &str) -> Option<Checksum> {
+        sri.split_whitespace()
+            .filter_map(|token| {
+                let (algo, digest) = token.split_once('-')?;
+                let algorithm = match algo {
+                    "sha1" => Algorithm::Sha1,
+                    "sha256" => Algorithm::Sha256,
+                    "sha384" => Algorithm::Sha384,
+                    "sha512" => Algorithm::Sha512,
+                    _ => return None,
+                };
+                // options such as `?foo` may follow the digest
+                let digest = digest.split('?').next()?;
+                let digest = general_purpose::STANDARD.decode(digest).ok()?;
+                Some(Checksum { algorithm, digest })
+            })
+            .max_by_key(|c| c.algorithm)
+    }
+
+    /// Parses the hex encoded sha1 from npm's legacy `shasum` field.
+    pub fn from_shasum(hex: &str) -> Option<Checksum> {
+        let hex = hex.trim();
+        if hex.len() != 40 {
+            return None;
+        }
+        Some(Checksum {
+            algorithm: Algorithm::Sha1,
+            digest: decode_hex(hex)?,
+        })
+    }
+
+    /// Parses a hex encoded sha256, either GitHub's `sha256:<hex>` asset digest
+    /// or the first word of a `sha256sum` style checksum file.
+    pub fn from_sha256(text: &str) -> Option<Checksum> {
+        let text = text.trim();
+        let hex = text.strip_prefix("sha256:").unwrap_or(text);
+        let hex = hex.split_whitespace().next()?;
+        if hex.len() != 64 {
+            return None;
+        }
+        Some(Checksum {
+            algorithm: Algorithm::Sha256,
+            digest: decode_hex(hex)?,
+        })
+    }
+
+    /// The checksum npm published for a tarball, preferring `integrity` over `shasum`.
+    pub fn for_dist(dist: &Dist) -> Option<Checksum> {
+        dist.integrity
+            .as_deref()
+            .and_then(Checksum::parse_sri)
+            .or_else(|| dist.shasum.as_deref().and_then(Checksum::from_shasum))
+    }
+
+    pub fn to_sri(&self) -> String {
+        format!(
+            "{}-{}",
+            self.algorithm,
+            general_purpose::STANDARD.encode(&self.digest)
+        )
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+enum Hasher {
+    Sha1(Sha1),
+    Sha256(Sha256),
+    Sha384(Sha384),
+    Sha512(Sha512),
+}
+
+/// Hashes data as it arrives and compares it with the expected checksum at the end.
+pub struct Verifier {
+    expected: Checksum,
+    hasher: Hasher,
+}
+
+impl Verifier {
+    pub fn new(expected: Checksum) -> Self {
+        let hasher = match expected.algorithm {
+            Algorithm::Sha1 => Hasher::Sha1(Sha1::new()),
+            Algorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            Algorithm::Sha384 => Hasher::Sha384(Sha384::new()),
+            Algorithm::Sha512 => Hasher::Sha512(Sha512::new()),
+        };
+        Self { expected, hasher }
+    }
+
+    /// Verifies against the checksum in `dist`, refusing tarballs that publish none.
+    pub fn for_dist(dist: &Dist) -> Result<Self, FetchError> {
+        match Checksum::for_dist(dist) {
+            Some(expected) => Ok(Self::new(expected)),
+            None => Err(FetchError::Integrity {
+                url: dist.tarball.clone(),
+                message: String::from("the registry published no usable integrity or shasum"),
+            }),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match &mut self.hasher {
+            Hasher::Sha1(h) => h.update(data),
+            Hasher::Sha256(h) => h.update(data),
+            Hasher::Sha384(h) => h.update(data),
+            Hasher::Sha512(h) => h.update(data),
+        }
+    }
+
+    pub fn finish(self, url: &str) -> Result<(), FetchError> {
+        let actual = match self.hasher {
+            Hasher::Sha1(h) => h.finalize().to_vec(),
+            Hasher::Sha256(h) => h.finalize().to_vec(),
+            Hasher::Sha384(h) => h.finalize().to_vec(),
+            Hasher::Sha512(h) => h.finalize().to_vec(),
+        };
+        if actual != self.expected.digest {
+            let actual = Checksum {
+                algorithm: self.expected.algorithm,
+                digest: actual,
+            };
+            return Err(FetchError::Integrity {
+                url: url.to_string(),
+                message: format!(
+                    "expected {}, got {}",
+                    self.expected.to_sri(),
+                    actual.to_sri()
+                ),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dist(integrity: Option<String>, shasum: Option<&str>) -> Dist {
+        Dist {
+            tarball: String::from("http://registry/op.gg.tgz"),
+            integrity,
+            shasum: shasum.map(String::from),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn verifies_sri_and_shasum() {
+        let data = b"champr";
+        let sri = format!(
+            "sha1-AAAA sha512-{}",
+            general_purpose::STANDARD.encode(Sha512::digest(data))
+        );
+        let mut v = Verifier::for_dist(&dist(Some(sri), None)).unwrap();
+        v.update(&data[..3]);
+        v.update(&data[3..]);
+        assert!(v.finish("u").is_ok());
+
+        let shasum = Sha1::digest(data)
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<String>();
+        let mut v = Verifier::for_dist(&dist(None, Some(&shasum))).unwrap();
+        v.update(b"tampered");
+        assert!(matches!(v.finish("u"), Err(FetchError::Integrity { .. })));
+
+        assert!(Verifier::for_dist(&dist(None, None)).is_err());
+    }
+}
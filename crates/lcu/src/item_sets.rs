@@ -0,0 +1,672 @@
+//! Writes item sets into the game's `Config/Champions` folder in two steps:
+//! `ItemSetWriter::plan` compares the builds with what is on disk without
+//! touching anything, `ItemSetWriter::execute` then carries the plan out.
+
+use std::{
+    collections::BTreeSet,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use kv_log_macro::{info, warn};
+
+use crate::{
+    backup::{self, BACKUP_DIR},
+    builds::{BuildSection, ItemBuild},
+    cancel::{self, CancellationToken},
+    changelog::{self, AppliedBuild, ChangelogEntry, HISTORY_FILE},
+    filters::BuildFilter,
+    items::ItemChange,
+    labels::Labels,
+    manifest::{self, Manifest, ManifestEntry, MANIFEST_FILE},
+    pool,
+    progress::{ApplyStage, Progress},
+};
+
+/// The folder the client reads item sets from.
+pub fn champions_dir(lol_dir: &str, is_tencent: bool) -> PathBuf {
+    if is_tencent {
+        Path::new(lol_dir).join("Game/Config/Champions")
+    } else {
+        Path::new(lol_dir).join("Config/Champions")
+    }
+}
+
+/// How item sets are ordered in the shop, the client lists higher `sortrank`s first.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Ranking {
+    /// Sources, the preferred one first. Others rank below all of them.
+    pub sources: Vec<String>,
+    /// Positions whose sets rank above those of other positions.
+    pub positions: Vec<String>,
+}
+
+impl Ranking {
+    pub fn new(sources: &[String], positions: &[String]) -> Self {
+        Self {
+            sources: sources.to_vec(),
+            positions: positions.to_vec(),
+        }
+    }
+
+    /// The `sortrank` of the `order`th item set of `source` for `position`.
+    pub fn sortrank(&self, source: &str, position: &str, order: usize) -> i64 {
+        let tier = self
+            .sources
+            .iter()
+            .position(|s| s == source)
+            .map_or(0, |idx| self.sources.len() - idx) as i64;
+        let position = pool::normalize_position(position);
+        let preferred = self
+            .positions
+            .iter()
+            .any(|p| pool::normalize_position(p) == position);
+        tier * 1000 + if preferred { 500 } else { 0 } - order.min(499) as i64
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Create,
+    Overwrite,
+    Delete,
+}
+
+impl fmt::Display for ChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self {
+            ChangeKind::Create => "create",
+            ChangeKind::Overwrite => "overwrite",
+            ChangeKind::Delete => "delete",
+        };
+        write!(f, "{label}")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedChange {
+    pub path: PathBuf,
+    pub kind: ChangeKind,
+    pub source: String,
+    pub champion: String,
+    pub old_size: Option<u64>,
+    pub new_size: Option<u64>,
+    /// Changed lines prefixed with `-` or `+`, only for overwrites.
+    pub diff: String,
+    contents: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Plan {
+    pub changes: Vec<PlannedChange>,
+    /// Files that already hold exactly what would be written.
+    pub unchanged: usize,
+    /// Item ids of the builds missing from the current patch, and what became of them.
+    pub item_changes: Vec<ItemChange>,
+    /// Summaries of the builds, recorded in the history once written.
+    pub applied: Vec<AppliedBuild>,
+}
+
+impl Plan {
+    pub fn count(&self, kind: ChangeKind) -> usize {
+        self.changes.iter().filter(|c| c.kind == kind).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn extend(&mut self, other: Plan) {
+        self.changes.extend(other.changes);
+        self.unchanged += other.unchanged;
+        self.item_changes.extend(other.item_changes);
+        self.applied.extend(other.applied);
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "{} to create, {} to overwrite, {} to delete, {} unchanged",
+            self.count(ChangeKind::Create),
+            self.count(ChangeKind::Overwrite),
+            self.count(ChangeKind::Delete),
+            self.unchanged
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct WriteReport {
+    pub written: usize,
+    pub deleted: usize,
+    /// What changed in the builds since they were last applied.
+    pub changelog: Vec<ChangelogEntry>,
+    pub errors: Vec<(PathBuf, io::Error)>,
+}
+
+impl WriteReport {
+    /// Fails with every file that could not be written, if any.
+    pub fn into_result(self) -> io::Result<Self> {
+        if self.errors.is_empty() {
+            return Ok(self);
+        }
+        let message = self
+            .errors
+            .iter()
+            .map(|(path, err)| format!("{}: {}", path.display(), err))
+            .collect::<Vec<_>>()
+            .join("; ");
+        Err(io::Error::other(message))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ItemSetWriter {
+    dir: PathBuf,
+    manifest_path: PathBuf,
+    backup_dir: PathBuf,
+    history_path: PathBuf,
+    ranking: Ranking,
+    labels: Labels,
+    filter: BuildFilter,
+}
+
+impl ItemSetWriter {
+    /// Writes below `dir`, the `Config/Champions` folder. Item sets keep the
+    /// order and labels of their source unless `with_ranking` and
+    /// `with_labels` are given, and no build is filtered out unless
+    /// `with_filter` is.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            manifest_path: PathBuf::from(MANIFEST_FILE),
+            backup_dir: PathBuf::from(BACKUP_DIR),
+            history_path: PathBuf::from(HISTORY_FILE),
+            ranking: Ranking::default(),
+            labels: Labels::default(),
+            filter: BuildFilter::default(),
+        }
+    }
+
+    pub fn with_ranking(mut self, ranking: Ranking) -> Self {
+        self.ranking = ranking;
+        self
+    }
+
+    pub fn with_labels(mut self, labels: Labels) -> Self {
+        self.labels = labels;
+        self
+    }
+
+    /// The thresholds callers apply to the builds before planning them.
+    pub fn with_filter(mut self, filter: BuildFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn filter(&self) -> BuildFilter {
+        self.filter
+    }
+
+    /// Keeps track of the written files in `path` instead of `MANIFEST_FILE`.
+    pub fn with_manifest(mut self, path: impl Into<PathBuf>) -> Self {
+        self.manifest_path = path.into();
+        self
+    }
+
+    /// Keeps the snapshots taken before writing in `dir` instead of `BACKUP_DIR`.
+    pub fn with_backups(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.backup_dir = dir.into();
+        self
+    }
+
+    /// Keeps the history of applied builds in `path` instead of `HISTORY_FILE`.
+    pub fn with_history(mut self, path: impl Into<PathBuf>) -> Self {
+        self.history_path = path.into();
+        self
+    }
+
+    pub fn history_path(&self) -> &Path {
+        &self.history_path
+    }
+
+    pub fn backup_dir(&self) -> &Path {
+        &self.backup_dir
+    }
+
+    /// Snapshots the folder, then drops the snapshots the retention policy no
+    /// longer keeps. Called once per apply run, before its first write.
+    pub fn back_up(&self) -> io::Result<()> {
+        let _lock = manifest::lock();
+        backup::snapshot(&self.dir, &self.backup_dir).inspect_err(|err| {
+            warn!("back up {:?}: {}", &self.dir, err);
+        })?;
+        if let Err(err) = backup::prune(&self.backup_dir, backup::retention()) {
+            warn!("prune backups in {:?}: {}", &self.backup_dir, err);
+        }
+        Ok(())
+    }
+
+    pub fn for_game(lol_dir: &str, is_tencent: bool) -> Self {
+        Self::new(champions_dir(lol_dir, is_tencent))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn recommended_dir(&self, champion_alias: &str) -> PathBuf {
+        self.dir.join(champion_alias).join("Recommended")
+    }
+
+    fn file_prefix(source: &str, champion_alias: &str) -> String {
+        format!("{}_{champion_alias}_", source.replace('.', "_"))
+    }
+
+    /// The item set files `sections` turn into, with their contents. The
+    /// `sortrank` of each set is replaced according to the ranking, titles and
+    /// block labels according to the label rules.
+    pub fn render(
+        &self,
+        source: &str,
+        champion_alias: &str,
+        sections: &[BuildSection],
+    ) -> io::Result<Vec<(PathBuf, String)>> {
+        let parent = self.recommended_dir(champion_alias);
+        let prefix = Self::file_prefix(source, champion_alias);
+
+        let mut files = vec![];
+        for (idx, section) in sections.iter().enumerate() {
+            let pos = &section.position;
+            for (iidx, item) in section.item_builds.iter().enumerate() {
+                let path = parent.join(format!("{prefix}{pos}_{idx}_{iidx}.json"));
+                let item = ItemBuild {
+                    sortrank: self.ranking.sortrank(source, pos, files.len()),
+                    ..self.labels.apply(source, section, item)
+                };
+                files.push((path, serde_json::to_string_pretty(&item)?));
+            }
+        }
+        Ok(files)
+    }
+
+    /// Compares the item sets of `champion_alias` from `source` with the files on
+    /// disk. Files ChampR wrote earlier for them that are no longer produced get
+    /// deleted, unless they were edited since.
+    pub fn plan(
+        &self,
+        source: &str,
+        champion_alias: &str,
+        sections: &[BuildSection],
+    ) -> io::Result<Plan> {
+        let files = self.render(source, champion_alias, sections)?;
+        let change = |path: &Path, kind, old_size, contents: Option<&String>, diff| PlannedChange {
+            path: path.to_path_buf(),
+            kind,
+            source: source.to_string(),
+            champion: champion_alias.to_string(),
+            old_size,
+            new_size: contents.map(|c| c.len() as u64),
+            diff,
+            contents: contents.cloned(),
+        };
+
+        let mut plan = Plan {
+            applied: changelog::summarize(source, sections),
+            ..Default::default()
+        };
+        for (path, contents) in files.iter() {
+            match fs::read_to_string(path) {
+                Ok(old) if &old == contents => plan.unchanged += 1,
+                Ok(old) => plan.changes.push(change(
+                    path,
+                    ChangeKind::Overwrite,
+                    Some(old.len() as u64),
+                    Some(contents),
+                    line_diff(&old, contents),
+                )),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => plan.changes.push(change(
+                    path,
+                    ChangeKind::Create,
+                    None,
+                    Some(contents),
+                    String::new(),
+                )),
+                Err(err) => return Err(err),
+            }
+        }
+
+        let manifest = Manifest::load(&self.manifest_path)?;
+        for path in manifest.files_of(source, champion_alias) {
+            if files.iter().any(|(p, _)| p == path) || !manifest.owns(path) {
+                continue;
+            }
+            let old_size = fs::metadata(path).ok().map(|m| m.len());
+            plan.changes.push(change(
+                path,
+                ChangeKind::Delete,
+                old_size,
+                None,
+                String::new(),
+            ));
+        }
+
+        Ok(plan)
+    }
+
+    /// The champions ChampR wrote item sets of `source` for below the folder.
+    pub fn champions_of(&self, source: &str) -> io::Result<BTreeSet<String>> {
+        let manifest = Manifest::load(&self.manifest_path)?;
+        Ok(manifest
+            .files
+            .iter()
+            .filter(|(path, entry)| path.starts_with(&self.dir) && entry.source == source)
+            .map(|(_, entry)| entry.champion.clone())
+            .collect())
+    }
+
+    /// Deletes every item set ChampR wrote below the folder, which leaves it as
+    /// it was before ChampR. Files edited since they were written are kept.
+    pub fn plan_removal(&self) -> io::Result<Plan> {
+        self.plan_removal_where(|_| true)
+    }
+
+    /// Like `plan_removal`, limited to the item sets written for `source`.
+    pub fn plan_source_removal(&self, source: &str) -> io::Result<Plan> {
+        self.plan_removal_where(|entry| entry.source == source)
+    }
+
+    fn plan_removal_where(&self, keep: impl Fn(&ManifestEntry) -> bool) -> io::Result<Plan> {
+        let manifest = Manifest::load(&self.manifest_path)?;
+        let changes = manifest
+            .files
+            .iter()
+            .filter(|(path, entry)| {
+                path.starts_with(&self.dir) && keep(entry) && manifest.owns(path)
+            })
+            .map(|(path, entry)| PlannedChange {
+                path: path.clone(),
+                kind: ChangeKind::Delete,
+                source: entry.source.clone(),
+                champion: entry.champion.clone(),
+                old_size: fs::metadata(path).ok().map(|m| m.len()),
+                new_size: None,
+                diff: String::new(),
+                contents: None,
+            })
+            .collect();
+
+        Ok(Plan {
+            changes,
+            ..Default::default()
+        })
+    }
+
+    /// Removes the `Recommended` and champion folders emptied by deleting `path`.
+    fn prune_empty_dirs(&self, path: &Path) {
+        for dir in path.ancestors().skip(1).take(2) {
+            if dir == self.dir || !dir.starts_with(&self.dir) || fs::remove_dir(dir).is_err() {
+                break;
+            }
+        }
+    }
+
+    /// Carries out `plan`, writing every file through a temporary sibling and a
+    /// rename. A file that fails does not stop the others; it ends up in the
+    /// report instead. Once `cancel` fires, the remaining files are left alone.
+    /// Taking a snapshot first is up to the caller, see `back_up`.
+    ///
+    /// Once every file is written, the builds of the plan are recorded in the
+    /// history, and what changed since they were last applied is reported.
+    pub fn execute(
+        &self,
+        plan: &Plan,
+        progress: &Progress,
+        cancel: &CancellationToken,
+    ) -> WriteReport {
+        let mut report = WriteReport::default();
+        let total = plan.changes.len();
+        progress.emit(ApplyStage::Writing { done: 0, total });
+
+        let _lock = manifest::lock();
+        let mut manifest = match Manifest::load(&self.manifest_path) {
+            Ok(manifest) => manifest,
+            Err(err) => {
+                report.errors.push((self.manifest_path.clone(), err));
+                return report;
+            }
+        };
+
+        for (done, change) in plan.changes.iter().enumerate() {
+            if cancel::check(cancel).is_err() {
+                break;
+            }
+            let result = match &change.contents {
+                Some(contents) => change
+                    .path
+                    .parent()
+                    .map_or(Ok(()), fs::create_dir_all)
+                    .and_then(|_| cancel::write_atomically(&change.path, contents.as_bytes())),
+                None => match fs::remove_file(&change.path) {
+                    Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+                    _ => Ok(()),
+                },
+            };
+            match (result, &change.contents) {
+                (Ok(_), Some(contents)) => {
+                    manifest.record(
+                        &change.path,
+                        &change.source,
+                        &change.champion,
+                        contents.as_bytes(),
+                    );
+                    report.written += 1;
+                }
+                (Ok(_), None) => {
+                    manifest.forget(&change.path);
+                    self.prune_empty_dirs(&change.path);
+                    report.deleted += 1;
+                }
+                (Err(err), _) => {
+                    warn!("{} {:?}: {}", change.kind, &change.path, err);
+                    report.errors.push((change.path.clone(), err));
+                }
+            }
+            progress.emit(ApplyStage::Writing {
+                done: done + 1,
+                total,
+            });
+        }
+
+        if report.errors.is_empty() && cancel::check(cancel).is_ok() {
+            match changelog::record(&self.history_path, &plan.applied) {
+                Ok(entries) => report.changelog = entries,
+                Err(err) => warn!("record build history {:?}: {}", &self.history_path, err),
+            }
+        }
+
+        manifest.forget_missing();
+        if let Err(err) = manifest.save(&self.manifest_path) {
+            warn!("save manifest {:?}: {}", &self.manifest_path, err);
+            report.errors.push((self.manifest_path.clone(), err));
+        }
+
+        info!(
+            "wrote {} and deleted {} item sets in {:?}",
+            report.written, report.deleted, &self.dir
+        );
+        report
+    }
+}
+
+/// A minimal line diff of `old` and `new`, listing removed and added lines.
+pub fn line_diff(old: &str, new: &str) -> String {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+
+    // longest common subsequence, item sets are only a few hundred lines
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            diff.push(format!("-{}", old[i]));
+            i += 1;
+        } else {
+            diff.push(format!("+{}", new[j]));
+            j += 1;
+        }
+    }
+    diff.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server;
+
+    fn section(position: &str, titles: &[&str]) -> BuildSection {
+        BuildSection {
+            alias: String::from("Ahri"),
+            position: position.to_string(),
+            item_builds: titles
+                .iter()
+                .map(|title| ItemBuild {
+                    title: title.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn plans_before_writing_anything() {
+        let lol_dir = test_server::temp_dir("item-sets");
+        let writer = ItemSetWriter::for_game(&lol_dir.to_string_lossy(), false)
+            .with_manifest(lol_dir.join(MANIFEST_FILE))
+            .with_history(lol_dir.join(HISTORY_FILE))
+            .with_backups(lol_dir.join(BACKUP_DIR));
+        let recommended = writer.recommended_dir("Ahri");
+
+        let plan = writer
+            .plan("op.gg", "Ahri", &[section("mid", &["a", "b"])])
+            .unwrap();
+        assert_eq!(plan.count(ChangeKind::Create), 2);
+        assert!(!recommended.exists());
+
+        let report = writer
+            .execute(&plan, &Progress::default(), &CancellationToken::new())
+            .into_result()
+            .unwrap();
+        assert_eq!(report.written, 2);
+        assert!(recommended.join("op_gg_Ahri_mid_0_0.json").exists());
+
+        fs::write(recommended.join("my_own_set.json"), "{}").unwrap();
+        let plan = writer
+            .plan("op.gg", "Ahri", &[section("mid", &["a2"])])
+            .unwrap();
+        assert_eq!(plan.count(ChangeKind::Overwrite), 1);
+        assert_eq!(plan.count(ChangeKind::Delete), 1);
+        assert_eq!(
+            plan.changes[0].diff,
+            "-  \"title\": \"a\",\n+  \"title\": \"a2\","
+        );
+
+        writer.back_up().unwrap();
+        writer.execute(&plan, &Progress::default(), &CancellationToken::new());
+        assert_eq!(backup::list(writer.backup_dir()).unwrap().len(), 1);
+        assert!(!recommended.join("op_gg_Ahri_mid_0_1.json").exists());
+        assert!(recommended.join("my_own_set.json").exists());
+
+        let plan = writer
+            .plan("op.gg", "Ahri", &[section("mid", &["a2"])])
+            .unwrap();
+        assert!(plan.is_empty());
+        assert_eq!(plan.unchanged, 1);
+    }
+
+    #[test]
+    fn removes_only_what_it_wrote() {
+        let lol_dir = test_server::temp_dir("item-sets-cleanup");
+        let writer = ItemSetWriter::for_game(&lol_dir.to_string_lossy(), true)
+            .with_manifest(lol_dir.join(MANIFEST_FILE))
+            .with_history(lol_dir.join(HISTORY_FILE))
+            .with_backups(lol_dir.join(BACKUP_DIR));
+        let ahri = writer.recommended_dir("Ahri");
+        fs::create_dir_all(&ahri).unwrap();
+        // named like ChampR's own files, but not written by it
+        fs::write(ahri.join("op_gg_Ahri_top_0_0.json"), "{}").unwrap();
+
+        for (source, position) in [("op.gg", "mid"), ("lolalytics", "top")] {
+            let plan = writer
+                .plan(source, "Ahri", &[section(position, &["a"])])
+                .unwrap();
+            writer.execute(&plan, &Progress::default(), &CancellationToken::new());
+        }
+        let zed = ItemSetWriter::new(writer.dir())
+            .with_manifest(lol_dir.join(MANIFEST_FILE))
+            .with_history(lol_dir.join(HISTORY_FILE))
+            .with_backups(lol_dir.join(BACKUP_DIR))
+            .plan("op.gg", "Zed", &[section("mid", &["z"])])
+            .unwrap();
+        writer.execute(&zed, &Progress::default(), &CancellationToken::new());
+        fs::write(ahri.join("lolalytics_Ahri_top_0_0.json"), "edited").unwrap();
+
+        let plan = writer.plan("op.gg", "Ahri", &[]).unwrap();
+        assert_eq!(plan.count(ChangeKind::Delete), 1);
+
+        let plan = writer.plan_removal().unwrap();
+        assert_eq!(plan.count(ChangeKind::Delete), 2);
+        writer
+            .execute(&plan, &Progress::default(), &CancellationToken::new())
+            .into_result()
+            .unwrap();
+        assert!(ahri.join("op_gg_Ahri_top_0_0.json").exists());
+        assert!(ahri.join("lolalytics_Ahri_top_0_0.json").exists());
+        assert!(!ahri.join("op_gg_Ahri_mid_0_0.json").exists());
+        assert!(!writer.dir().join("Zed").exists());
+
+        let manifest = Manifest::load(&lol_dir.join(MANIFEST_FILE)).unwrap();
+        assert_eq!(manifest.files.len(), 1);
+    }
+
+    #[test]
+    fn ranks_preferred_sources_and_positions_first() {
+        let writer = ItemSetWriter::new("Champions").with_ranking(Ranking {
+            sources: vec![String::from("u.gg"), String::from("op.gg")],
+            positions: vec![String::from("Support")],
+        });
+        let sortranks = |source| {
+            writer
+                .render(
+                    source,
+                    "Ahri",
+                    &[section("mid", &["a", "b"]), section("support", &["c"])],
+                )
+                .unwrap()
+                .into_iter()
+                .map(|(_, contents)| {
+                    serde_json::from_str::<ItemBuild>(&contents)
+                        .unwrap()
+                        .sortrank
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sortranks("u.gg"), [2000, 1999, 2498]);
+        assert_eq!(sortranks("op.gg"), [1000, 999, 1498]);
+        assert_eq!(sortranks("lolalytics"), [0, -1, 498]);
+    }
+}
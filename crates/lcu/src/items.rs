@@ -0,0 +1,263 @@
+//! The items of the current patch, used to catch item ids of stale builds
+//! before they end up as empty slots in the shop.
+//!
+//! The catalog comes from the LCU while the client runs, Data Dragon otherwise.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, RwLock},
+};
+
+use kv_log_macro::{info, warn};
+use lazy_static::lazy_static;
+
+use crate::{
+    api::{self, GameItem},
+    builds::BuildSection,
+    ddragon::{self, ItemsMap},
+    fetch_error::FetchError,
+    lcu_error::LcuError,
+};
+
+#[derive(Default, Debug, Clone)]
+pub struct ItemCatalog {
+    names: HashMap<String, String>,
+}
+
+impl ItemCatalog {
+    pub fn from_game_items(items: &[GameItem]) -> Self {
+        Self {
+            names: items
+                .iter()
+                .map(|item| (item.id.to_string(), item.name.clone()))
+                .collect(),
+        }
+    }
+
+    pub fn from_data_dragon(items: &ItemsMap) -> Self {
+        Self {
+            names: items
+                .iter()
+                .map(|(id, item)| (id.clone(), item.name.clone()))
+                .collect(),
+        }
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.names.contains_key(id)
+    }
+
+    pub fn name(&self, id: &str) -> Option<&str> {
+        self.names.get(id).map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Replaces unknown item ids in `sections` through `substitutions`, or drops
+    /// them when there is no known replacement. Blocks left without items are
+    /// dropped as well. Nothing is checked while the catalog is empty.
+    pub fn check_sections(
+        &self,
+        source: &str,
+        sections: &mut [BuildSection],
+        substitutions: &HashMap<String, String>,
+    ) -> Vec<ItemChange> {
+        let mut changes = vec![];
+        if self.is_empty() {
+            return changes;
+        }
+
+        for section in sections.iter_mut() {
+            let mut found = vec![];
+            for build in section.item_builds.iter_mut() {
+                for block in build.blocks.iter_mut() {
+                    let Some(items) = block.items.as_mut() else {
+                        continue;
+                    };
+                    items.retain_mut(|item| {
+                        if self.contains(&item.id) {
+                            return true;
+                        }
+                        match substitutions.get(&item.id).filter(|to| self.contains(to)) {
+                            Some(to) => {
+                                found.push(ItemChangeKind::Substituted {
+                                    from: item.id.clone(),
+                                    to: to.clone(),
+                                });
+                                item.id = to.clone();
+                                true
+                            }
+                            None => {
+                                found.push(ItemChangeKind::Dropped(item.id.clone()));
+                                false
+                            }
+                        }
+                    });
+                }
+                build
+                    .blocks
+                    .retain(|b| b.items.as_ref().is_none_or(|items| !items.is_empty()));
+            }
+            changes.extend(found.into_iter().map(|kind| ItemChange {
+                source: source.to_string(),
+                champion: section.alias.clone(),
+                position: section.position.clone(),
+                kind,
+            }));
+        }
+        changes
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ItemChangeKind {
+    Dropped(String),
+    Substituted { from: String, to: String },
+}
+
+/// An item id of a build that is not in the current patch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ItemChange {
+    pub source: String,
+    pub champion: String,
+    pub position: String,
+    pub kind: ItemChangeKind,
+}
+
+impl fmt::Display for ItemChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}: ", self.champion, self.position)?;
+        match &self.kind {
+            ItemChangeKind::Dropped(id) => write!(f, "dropped unknown item {id}"),
+            ItemChangeKind::Substituted { from, to } => write!(f, "replaced {from} with {to}"),
+        }
+    }
+}
+
+lazy_static! {
+    static ref SHARED: RwLock<Arc<ItemCatalog>> = RwLock::new(Default::default());
+    static ref SUBSTITUTIONS: RwLock<HashMap<String, String>> = RwLock::new(HashMap::new());
+}
+
+/// Sets the table of replacements for removed items, old id to new id.
+pub fn configure(substitutions: &HashMap<String, String>) {
+    *SUBSTITUTIONS.write().unwrap() = substitutions.clone();
+}
+
+pub fn substitutions() -> HashMap<String, String> {
+    SUBSTITUTIONS.read().unwrap().clone()
+}
+
+/// The catalog as last loaded, empty until a load has succeeded once.
+pub fn shared() -> Arc<ItemCatalog> {
+    SHARED.read().unwrap().clone()
+}
+
+fn set(catalog: ItemCatalog) -> Arc<ItemCatalog> {
+    let catalog = Arc::new(catalog);
+    *SHARED.write().unwrap() = catalog.clone();
+    catalog
+}
+
+/// Loads the items of the patch the client runs.
+pub async fn load_from_lcu(endpoint: String) -> Result<Arc<ItemCatalog>, LcuError> {
+    let items = api::list_game_items(&endpoint).await?;
+    info!("loaded {} items from the client", items.len());
+    Ok(set(ItemCatalog::from_game_items(&items)))
+}
+
+/// Loads the items of the latest patch from Data Dragon.
+pub async fn load_from_data_dragon() -> Result<Arc<ItemCatalog>, FetchError> {
+    let ddragon = ddragon::shared();
+    let version = ddragon.latest_version().await?;
+    let items = ddragon.items(&version).await?;
+    info!(
+        "loaded {} items of {} from Data Dragon",
+        items.len(),
+        version
+    );
+    Ok(set(ItemCatalog::from_data_dragon(&items)))
+}
+
+/// The shared catalog, loading it from Data Dragon if that never happened. An
+/// empty catalog is returned when loading fails, which skips the checks.
+pub async fn get() -> Arc<ItemCatalog> {
+    let catalog = shared();
+    if !catalog.is_empty() {
+        return catalog;
+    }
+    load_from_data_dragon().await.unwrap_or_else(|err| {
+        warn!("load items, item ids are not checked: {}", err);
+        catalog
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builds::{Block, Item, ItemBuild};
+
+    #[test]
+    fn drops_or_substitutes_unknown_items() {
+        let catalog = ItemCatalog::from_game_items(&[
+            GameItem {
+                id: 1056,
+                name: String::from("Doran's Ring"),
+            },
+            GameItem {
+                id: 3078,
+                name: String::from("Trinity Force"),
+            },
+        ]);
+        let block = |name: &str, ids: &[&str]| Block {
+            type_field: name.to_string(),
+            items: Some(
+                ids.iter()
+                    .map(|id| Item {
+                        id: id.to_string(),
+                        count: 1,
+                    })
+                    .collect(),
+            ),
+        };
+        let mut sections = vec![BuildSection {
+            alias: String::from("Ahri"),
+            position: String::from("mid"),
+            item_builds: vec![ItemBuild {
+                blocks: vec![
+                    block("Starter", &["1056", "2003"]),
+                    block("Core", &["6630"]),
+                    block("Removed", &["3041"]),
+                ],
+                ..Default::default()
+            }],
+            ..Default::default()
+        }];
+        let substitutions = HashMap::from([(String::from("6630"), String::from("3078"))]);
+
+        let changes = catalog.check_sections("op.gg", &mut sections, &substitutions);
+        assert_eq!(
+            changes.iter().map(|c| c.to_string()).collect::<Vec<_>>(),
+            [
+                "Ahri mid: dropped unknown item 2003",
+                "Ahri mid: replaced 6630 with 3078",
+                "Ahri mid: dropped unknown item 3041",
+            ]
+        );
+        let blocks = &sections[0].item_builds[0].blocks;
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].items.as_ref().unwrap().len(), 1);
+        assert_eq!(blocks[1].items.as_ref().unwrap()[0].id, "3078");
+
+        assert!(ItemCatalog::default()
+            .check_sections("op.gg", &mut sections, &substitutions)
+            .is_empty());
+    }
+}
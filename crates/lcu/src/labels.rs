@@ -0,0 +1,291 @@
+//! Titles and block labels of written item sets. Sources name them as they
+//! like; a title template and renaming rules make them consistent, and the
+//! common English labels are translated for the configured locale.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::builds::{Block, BuildSection, ItemBuild};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LabelRules {
+    /// Title of each item set, e.g. `{source} {position} {win_rate}% ({pick_count})`.
+    /// Also knows `{champion}`, `{alias}`, `{version}` and `{title}`, the
+    /// title the source gave. Empty keeps the source's title.
+    pub title_template: String,
+    /// Block labels to replace, matched ignoring case, e.g. `Starters` to `Start`.
+    pub block_renames: HashMap<String, String>,
+}
+
+/// English labels of the sources and their translations, by locale.
+const BUILT_IN: &[(&str, &[(&str, &str)])] = &[
+    (
+        "starters",
+        &[
+            ("zh_CN", "出门装"),
+            ("zh_TW", "出門裝"),
+            ("ko_KR", "시작 아이템"),
+        ],
+    ),
+    (
+        "starter items",
+        &[
+            ("zh_CN", "出门装"),
+            ("zh_TW", "出門裝"),
+            ("ko_KR", "시작 아이템"),
+        ],
+    ),
+    (
+        "core",
+        &[
+            ("zh_CN", "核心装备"),
+            ("zh_TW", "核心裝備"),
+            ("ko_KR", "핵심 아이템"),
+        ],
+    ),
+    (
+        "core items",
+        &[
+            ("zh_CN", "核心装备"),
+            ("zh_TW", "核心裝備"),
+            ("ko_KR", "핵심 아이템"),
+        ],
+    ),
+    (
+        "boots",
+        &[("zh_CN", "鞋子"), ("zh_TW", "鞋子"), ("ko_KR", "신발")],
+    ),
+    (
+        "situational",
+        &[
+            ("zh_CN", "可选装备"),
+            ("zh_TW", "可選裝備"),
+            ("ko_KR", "상황별 아이템"),
+        ],
+    ),
+    (
+        "consumables",
+        &[
+            ("zh_CN", "消耗品"),
+            ("zh_TW", "消耗品"),
+            ("ko_KR", "소모품"),
+        ],
+    ),
+    (
+        "top",
+        &[("zh_CN", "上单"), ("zh_TW", "上路"), ("ko_KR", "탑")],
+    ),
+    (
+        "jungle",
+        &[("zh_CN", "打野"), ("zh_TW", "打野"), ("ko_KR", "정글")],
+    ),
+    (
+        "mid",
+        &[("zh_CN", "中单"), ("zh_TW", "中路"), ("ko_KR", "미드")],
+    ),
+    (
+        "adc",
+        &[("zh_CN", "下路"), ("zh_TW", "下路"), ("ko_KR", "원딜")],
+    ),
+    (
+        "support",
+        &[("zh_CN", "辅助"), ("zh_TW", "輔助"), ("ko_KR", "서포터")],
+    ),
+];
+
+/// `label` in `locale`, if it is one of the built-in labels.
+pub fn translate(label: &str, locale: &str) -> Option<&'static str> {
+    let label = label.trim().to_lowercase();
+    BUILT_IN
+        .iter()
+        .find(|(english, _)| *english == label)
+        .and_then(|(_, translations)| translations.iter().find(|(l, _)| *l == locale))
+        .map(|(_, translated)| *translated)
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Labels {
+    rules: LabelRules,
+    locale: String,
+    sources: Vec<String>,
+}
+
+impl Labels {
+    /// Labels renamed by `rules`, with the built-in labels translated to
+    /// `locale`, e.g. `zh_CN`.
+    pub fn new(rules: LabelRules, locale: &str) -> Self {
+        Self {
+            rules,
+            locale: locale.trim().to_string(),
+            sources: vec![],
+        }
+    }
+
+    /// The sources merged block labels such as `op.gg: Core` may start with.
+    pub fn with_sources(mut self, sources: &[String]) -> Self {
+        self.sources = sources.to_vec();
+        self
+    }
+
+    fn localize(&self, label: &str) -> String {
+        translate(label, &self.locale).map_or_else(|| label.to_string(), String::from)
+    }
+
+    /// The label of a block: renamed by the rules, otherwise translated.
+    /// Merged labels such as `op.gg: Core` keep their source prefix, other
+    /// labels are taken as a whole, colons included.
+    pub fn block(&self, label: &str) -> String {
+        if let Some((source, label)) = label.split_once(": ") {
+            if self.sources.iter().any(|s| s == source) {
+                return format!("{source}: {}", self.block(label));
+            }
+        }
+        let renamed = self
+            .rules
+            .block_renames
+            .iter()
+            .find(|(from, _)| from.trim().eq_ignore_ascii_case(label.trim()));
+        match renamed {
+            Some((_, to)) => to.clone(),
+            None => self.localize(label),
+        }
+    }
+
+    /// The title of `build`, written by `source` for `section`. Placeholders
+    /// are only looked for in the template, not in the values put in.
+    pub fn title(&self, source: &str, section: &BuildSection, build: &ItemBuild) -> String {
+        let template = self.rules.title_template.trim();
+        if template.is_empty() {
+            return build.title.clone();
+        }
+        let win_rate = section
+            .win_rate
+            .map_or_else(|| String::from("?"), |rate| format!("{rate:.1}"));
+        let values = [
+            ("{source}", source.to_string()),
+            ("{champion}", section.name.clone()),
+            ("{alias}", section.alias.clone()),
+            ("{position}", self.localize(&section.position)),
+            ("{win_rate}", win_rate),
+            ("{pick_count}", section.pick_count.to_string()),
+            ("{version}", section.version.clone()),
+            ("{title}", build.title.clone()),
+        ];
+        let mut title = String::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            title.push_str(&rest[..start]);
+            rest = &rest[start..];
+            match values.iter().find(|(key, _)| rest.starts_with(key)) {
+                Some((key, value)) => {
+                    title.push_str(value);
+                    rest = &rest[key.len()..];
+                }
+                None => {
+                    title.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        title.push_str(rest);
+        title
+    }
+
+    /// `build` with its title and block labels replaced.
+    pub fn apply(&self, source: &str, section: &BuildSection, build: &ItemBuild) -> ItemBuild {
+        ItemBuild {
+            title: self.title(source, section, build),
+            blocks: build
+                .blocks
+                .iter()
+                .map(|block| Block {
+                    type_field: self.block(&block.type_field),
+                    ..block.clone()
+                })
+                .collect(),
+            ..build.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_titles_and_block_labels() {
+        let section = BuildSection {
+            alias: String::from("Ahri"),
+            name: String::from("Ahri"),
+            position: String::from("mid"),
+            pick_count: 1234,
+            win_rate: Some(52.345),
+            ..Default::default()
+        };
+        let build = ItemBuild {
+            title: String::from("op.gg mid"),
+            blocks: ["Starters", "core", "op.gg: Boots", "Full build"]
+                .iter()
+                .map(|label| Block {
+                    type_field: label.to_string(),
+                    items: None,
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let rules = LabelRules {
+            title_template: String::from("{source} {position} {win_rate}% ({pick_count})"),
+            block_renames: HashMap::from([(String::from("starters"), String::from("Start"))]),
+        };
+
+        let sources = [String::from("op.gg")];
+        let english = Labels::new(rules.clone(), "en_US")
+            .with_sources(&sources)
+            .apply("op.gg", &section, &build);
+        assert_eq!(english.title, "op.gg mid 52.3% (1234)");
+        assert_eq!(
+            english
+                .blocks
+                .iter()
+                .map(|b| b.type_field.as_str())
+                .collect::<Vec<_>>(),
+            ["Start", "core", "op.gg: Boots", "Full build"]
+        );
+
+        let chinese = Labels::new(rules, "zh_CN")
+            .with_sources(&sources)
+            .apply("op.gg", &section, &build);
+        assert_eq!(chinese.title, "op.gg 中单 52.3% (1234)");
+        assert_eq!(chinese.blocks[1].type_field, "核心装备");
+        assert_eq!(chinese.blocks[2].type_field, "op.gg: 鞋子");
+
+        assert_eq!(Labels::default().apply("op.gg", &section, &build), build);
+    }
+
+    #[test]
+    fn leaves_placeholders_in_values_alone() {
+        let section = BuildSection {
+            name: String::from("{version} {source}"),
+            position: String::from("mid"),
+            version: String::from("14.1"),
+            ..Default::default()
+        };
+        let rules = LabelRules {
+            title_template: String::from("{champion} {version} {unknown}"),
+            ..Default::default()
+        };
+        let title = Labels::new(rules, "en_US").title("op.gg", &section, &ItemBuild::default());
+        assert_eq!(title, "{version} {source} 14.1 {unknown}");
+    }
+
+    #[test]
+    fn only_strips_known_source_prefixes() {
+        let labels =
+            Labels::new(LabelRules::default(), "zh_CN").with_sources(&[String::from("op.gg")]);
+        assert_eq!(labels.block("op.gg: Core"), "op.gg: 核心装备");
+        assert_eq!(labels.block("Core: vs AP"), "Core: vs AP");
+        assert_eq!(labels.block("u.gg: Core"), "u.gg: Core");
+    }
+}
@@ -1,18 +1,110 @@
-#[derive(Debug, Clone)]
+use std::fmt;
+
+use crate::fetch_error::FetchError;
+
+/// Errors talking to the local League client.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum LcuError {
-    APIError(String),
+    Timeout {
+        endpoint: String,
+    },
+    /// Nothing is listening on the client port, usually because the client was closed.
+    ConnectionRefused {
+        endpoint: String,
+    },
+    /// The client rejected the remoting token, usually because it restarted.
+    Unauthorized,
+    Status {
+        endpoint: String,
+        status: u16,
+        body: String,
+    },
+    Decode {
+        endpoint: String,
+        path: String,
+        message: String,
+    },
+    NotFound(String),
+    Other(String),
+}
+
+impl fmt::Display for LcuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LcuError::Timeout { endpoint } => write!(
+                f,
+                "The League client did not answer {endpoint} in time. It may still be starting up."
+            ),
+            LcuError::ConnectionRefused { .. } => {
+                write!(f, "Could not reach the League client. Is it running?")
+            }
+            LcuError::Unauthorized => write!(
+                f,
+                "The League client rejected ChampR's token. Wait a moment for ChampR to reconnect, or restart the client."
+            ),
+            LcuError::Status {
+                endpoint,
+                status,
+                body,
+            } => {
+                write!(f, "The League client answered {endpoint} with HTTP {status}")?;
+                if !body.is_empty() {
+                    write!(f, ": {body}")?;
+                }
+                Ok(())
+            }
+            LcuError::Decode {
+                endpoint,
+                path,
+                message,
+            } => write!(
+                f,
+                "Unexpected data from the League client at {endpoint} (`{path}`): {message}. Updating ChampR may help."
+            ),
+            LcuError::NotFound(endpoint) => {
+                write!(f, "The League client has nothing at {endpoint}.")
+            }
+            LcuError::Other(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for LcuError {}
+
+impl From<FetchError> for LcuError {
+    fn from(error: FetchError) -> LcuError {
+        match error {
+            FetchError::Timeout { url } => LcuError::Timeout { endpoint: url },
+            FetchError::ConnectionRefused { url } => LcuError::ConnectionRefused { endpoint: url },
+            FetchError::Status {
+                status: 401 | 403, ..
+            } => LcuError::Unauthorized,
+            FetchError::Status { url, status, body } => LcuError::Status {
+                endpoint: url,
+                status,
+                body,
+            },
+            FetchError::Decode {
+                url, path, message, ..
+            } => LcuError::Decode {
+                endpoint: url,
+                path,
+                message,
+            },
+            FetchError::NotFound(endpoint) => LcuError::NotFound(endpoint),
+            other => LcuError::Other(format!("{:#}", anyhow::Error::new(other))),
+        }
+    }
 }
 
 impl From<reqwest::Error> for LcuError {
     fn from(error: reqwest::Error) -> LcuError {
-        println!("lcu api error: {:?}", error);
-        LcuError::APIError(error.to_string())
+        LcuError::from(FetchError::from(error))
     }
 }
 
 impl From<anyhow::Error> for LcuError {
     fn from(error: anyhow::Error) -> LcuError {
-        println!("lcu api error: {:?}", error);
-        LcuError::APIError(error.to_string())
+        LcuError::Other(format!("{:#}", error))
     }
 }
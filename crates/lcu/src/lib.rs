@@ -1,8 +1,35 @@
 pub mod api;
+pub mod archive;
+pub mod backup;
 pub mod builds;
+pub mod cache;
+pub mod cancel;
+pub mod cdragon;
+pub mod changelog;
+pub mod champions;
 pub mod cmd;
 pub mod constants;
+pub mod ddragon;
+pub mod endpoints;
+pub mod fetch_error;
+pub mod filters;
+pub mod integrity;
+pub mod item_sets;
+pub mod items;
+pub mod labels;
 pub mod lcu_error;
+pub mod local_source;
+pub mod manifest;
+pub mod merge;
+pub mod packages;
+pub mod patch;
+pub mod pool;
+pub mod progress;
+pub mod provider;
 pub mod source;
 pub mod task;
+pub mod updater;
 pub mod web;
+
+#[cfg(test)]
+pub(crate) mod test_server;
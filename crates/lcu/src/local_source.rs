@@ -0,0 +1,252 @@
+//! A folder of hand-written builds, laid out like an npm package: one JSON file
+//! per champion, each holding a `Vec<BuildSection>`.
+
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use eframe::egui;
+use kv_log_macro::info;
+use lazy_static::lazy_static;
+
+use crate::{
+    builds::BuildSection,
+    cancel::CancellationToken,
+    fetch_error::FetchError,
+    progress::{ApplyStage, Progress},
+    provider::{BuildProvider, Bundle},
+    source::SourceItem,
+    web,
+};
+
+/// The source value the local folder is selected and applied by.
+pub const LOCAL_SOURCE: &str = "local";
+pub const DEFAULT_LABEL: &str = "Local builds";
+
+const POLL_INTERVAL: Duration = Duration::from_millis(2000);
+
+lazy_static! {
+    static ref GENERATION: AtomicU64 = AtomicU64::new(0);
+}
+
+/// Bumped whenever a watched folder changes, so the UI knows to reload.
+pub fn generation() -> u64 {
+    GENERATION.load(Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone)]
+pub struct LocalFolderProvider {
+    label: String,
+    dir: PathBuf,
+}
+
+impl LocalFolderProvider {
+    pub fn new(label: &str, dir: impl Into<PathBuf>) -> Self {
+        let label = if label.trim().is_empty() {
+            DEFAULT_LABEL
+        } else {
+            label.trim()
+        };
+        Self {
+            label: label.to_string(),
+            dir: dir.into(),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    async fn read_all(&self) -> anyhow::Result<Bundle> {
+        if !self.dir.is_dir() {
+            return Err(FetchError::NotFound(format!("folder {}", self.dir.display())).into());
+        }
+        web::read_from_local_folder(&self.dir.to_string_lossy()).await
+    }
+}
+
+#[async_trait]
+impl BuildProvider for LocalFolderProvider {
+    fn id(&self) -> &str {
+        LOCAL_SOURCE
+    }
+
+    fn provides(&self, source: &str) -> bool {
+        source == LOCAL_SOURCE
+    }
+
+    async fn list_sources(&self) -> anyhow::Result<Vec<SourceItem>> {
+        if !self.dir.is_dir() {
+            return Err(FetchError::NotFound(format!("folder {}", self.dir.display())).into());
+        }
+        Ok(vec![SourceItem {
+            label: self.label.clone(),
+            value: LOCAL_SOURCE.to_string(),
+            is_aram: None,
+            is_urf: None,
+        }])
+    }
+
+    async fn list_builds(
+        &self,
+        _source: &str,
+        champion_alias: &str,
+    ) -> anyhow::Result<Vec<BuildSection>> {
+        // file names are up to whoever wrote them, so match on the content
+        self.read_all()
+            .await?
+            .into_iter()
+            .find(|sections| {
+                sections
+                    .first()
+                    .is_some_and(|s| s.alias.eq_ignore_ascii_case(champion_alias))
+            })
+            .ok_or_else(|| {
+                FetchError::NotFound(format!("{champion_alias} in {}", self.label)).into()
+            })
+    }
+
+    async fn fetch_bundle(
+        &self,
+        _source: &str,
+        progress: &Progress,
+        _cancel: &CancellationToken,
+    ) -> anyhow::Result<Bundle> {
+        progress.emit(ApplyStage::Resolving);
+        self.read_all().await
+    }
+}
+
+/// Changes whenever a JSON file in `dir` is added, removed or modified.
+pub fn fingerprint(dir: &Path) -> u64 {
+    let mut entries = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .filter(|e| e.path().extension().is_some_and(|ext| ext == "json"))
+                .filter_map(|e| {
+                    let meta = e.metadata().ok()?;
+                    Some((e.file_name(), meta.len(), meta.modified().ok()))
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    entries.sort();
+
+    let mut hasher = DefaultHasher::new();
+    entries.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Polls `dir` and bumps `generation()` when its builds change.
+pub async fn watch(dir: PathBuf, ui_ctx: Arc<Mutex<Option<egui::Context>>>) {
+    let mut last = fingerprint(&dir);
+    loop {
+        tokio::time::sleep(POLL_INTERVAL).await;
+
+        let current = fingerprint(&dir);
+        if current != last {
+            last = current;
+            info!("local builds in {:?} changed, reloading", &dir);
+            GENERATION.fetch_add(1, Ordering::Relaxed);
+
+            if let Some(ctx) = ui_ctx.lock().unwrap().as_ref() {
+                ctx.request_repaint();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{provider::Registry, test_server};
+
+    fn section(alias: &str) -> BuildSection {
+        BuildSection {
+            alias: alias.to_string(),
+            position: String::from("mid"),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn reads_builds_and_notices_changes() {
+        let dir = test_server::temp_dir("local");
+        fs::write(
+            dir.join("ahri-scrims.json"),
+            serde_json::to_string(&vec![section("Ahri")]).unwrap(),
+        )
+        .unwrap();
+        let provider = LocalFolderProvider::new("Scrims", &dir);
+
+        let sources = provider.list_sources().await.unwrap();
+        assert_eq!(
+            (sources[0].label.as_str(), sources[0].value.as_str()),
+            ("Scrims", LOCAL_SOURCE)
+        );
+        assert_eq!(
+            provider.list_builds(LOCAL_SOURCE, "ahri").await.unwrap(),
+            vec![section("Ahri")]
+        );
+        assert!(provider.list_builds(LOCAL_SOURCE, "Zed").await.is_err());
+
+        let before = fingerprint(&dir);
+        fs::write(
+            dir.join("Zed.json"),
+            serde_json::to_string(&vec![section("Zed")]).unwrap(),
+        )
+        .unwrap();
+        assert_ne!(fingerprint(&dir), before);
+        let bundle = provider
+            .fetch_bundle(
+                LOCAL_SOURCE,
+                &Progress::default(),
+                &CancellationToken::new(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(bundle.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn serves_its_source_once_registered() {
+        let dir = test_server::temp_dir("local");
+        fs::write(
+            dir.join("Ahri.json"),
+            serde_json::to_string(&vec![section("Ahri")]).unwrap(),
+        )
+        .unwrap();
+        let provider = LocalFolderProvider::new("", &dir);
+        assert!(provider.provides(LOCAL_SOURCE));
+        assert!(!provider.provides("op.gg"));
+        assert_eq!(
+            provider.list_sources().await.unwrap()[0].label,
+            DEFAULT_LABEL
+        );
+
+        // a registry of its own, the shared one is used by tests running alongside
+        let registry = Registry::default();
+        registry.register(Arc::new(provider));
+        assert_eq!(
+            registry.for_source(LOCAL_SOURCE).unwrap().id(),
+            LOCAL_SOURCE
+        );
+        assert_eq!(registry.for_source("op.gg").unwrap().id(), "npm");
+        assert_eq!(
+            registry.list_builds(LOCAL_SOURCE, "Ahri").await.unwrap(),
+            vec![section("Ahri")]
+        );
+        registry.unregister(LOCAL_SOURCE);
+        assert!(registry.for_source(LOCAL_SOURCE).is_err());
+    }
+}
@@ -0,0 +1,117 @@
+//! Remembers every item set file ChampR wrote, so cleaning up never touches
+//! item sets made by the player or other tools.
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::cancel;
+
+/// Stored next to `.settings.toml`.
+pub const MANIFEST_FILE: &str = ".item-sets.json";
+
+lazy_static! {
+    static ref LOCK: Mutex<()> = Mutex::new(());
+}
+
+/// Held while a manifest is read, changed and saved, so concurrent writers
+/// don't drop each other's entries.
+pub fn lock() -> MutexGuard<'static, ()> {
+    LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub source: String,
+    pub champion: String,
+    pub sha256: String,
+    /// Unix timestamp in seconds.
+    pub written_at: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    #[serde(default)]
+    pub files: BTreeMap<PathBuf, ManifestEntry>,
+}
+
+impl Manifest {
+    pub fn load(path: &Path) -> io::Result<Manifest> {
+        match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).map_err(|err| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: {}", path.display(), err),
+                )
+            }),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Manifest::default()),
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let content = serde_json::to_string_pretty(self)?;
+        cancel::write_atomically(path, content.as_bytes())
+    }
+
+    pub fn record(&mut self, path: &Path, source: &str, champion: &str, contents: &[u8]) {
+        let written_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        self.files.insert(
+            path.to_path_buf(),
+            ManifestEntry {
+                source: source.to_string(),
+                champion: champion.to_string(),
+                sha256: sha256_hex(contents),
+                written_at,
+            },
+        );
+    }
+
+    pub fn forget(&mut self, path: &Path) {
+        self.files.remove(path);
+    }
+
+    /// Drops entries whose files are gone.
+    pub fn forget_missing(&mut self) {
+        self.files.retain(|path, _| path.exists());
+    }
+
+    /// Whether `path` was written by ChampR and still holds what was written.
+    pub fn owns(&self, path: &Path) -> bool {
+        self.files.get(path).is_some_and(|entry| {
+            fs::read(path).is_ok_and(|contents| sha256_hex(&contents) == entry.sha256)
+        })
+    }
+
+    pub fn files_of<'a>(
+        &'a self,
+        source: &'a str,
+        champion: &'a str,
+    ) -> impl Iterator<Item = &'a PathBuf> {
+        self.files
+            .iter()
+            .filter(move |(_, e)| e.source == source && e.champion == champion)
+            .map(|(path, _)| path)
+    }
+}
+
+pub fn sha256_hex(contents: &[u8]) -> String {
+    Sha256::digest(contents)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
@@ -0,0 +1,203 @@
+//! Combines the builds of several sources into a single item set per champion
+//! and position, instead of one file per source.
+
+use std::collections::HashSet;
+
+use crate::{
+    builds::{Block, BuildSection, ItemBuild},
+    pool::normalize_position,
+};
+
+/// Written to the manifest and used as the file prefix of merged item sets.
+pub const MERGED_SOURCE: &str = "ChampR";
+
+/// The sections of one champion from one source.
+pub type SourceSections = (String, Vec<BuildSection>);
+
+/// Merges the sections of one champion, `sources` ordered by priority.
+///
+/// Positions are matched however the sources spell them, e.g. `middle` and
+/// `mid`. Each source contributes its blocks under a label naming it; a block
+/// holding the same items as one before it is dropped.
+pub fn merge_sections(sources: &[SourceSections]) -> Vec<BuildSection> {
+    let mut positions: Vec<String> = vec![];
+    for (_, sections) in sources.iter() {
+        for section in sections.iter() {
+            let position = normalize_position(&section.position);
+            if !positions.contains(&position) {
+                positions.push(position);
+            }
+        }
+    }
+
+    let mut merged = vec![];
+    for position in positions.iter() {
+        let matching = sources.iter().flat_map(|(source, sections)| {
+            sections
+                .iter()
+                .filter(move |s| normalize_position(&s.position) == *position)
+                .map(move |s| (source, s))
+        });
+
+        let mut base: Option<BuildSection> = None;
+        let mut build: Option<ItemBuild> = None;
+        let mut seen = HashSet::new();
+        for (source, section) in matching {
+            let base = base.get_or_insert_with(|| BuildSection {
+                position: position.clone(),
+                item_builds: vec![],
+                runes: vec![],
+                ..section.clone()
+            });
+            base.runes.extend(section.runes.iter().cloned());
+
+            for item_build in section.item_builds.iter() {
+                let build = build.get_or_insert_with(|| ItemBuild {
+                    title: format!("{MERGED_SOURCE} {position}"),
+                    blocks: vec![],
+                    ..item_build.clone()
+                });
+                for block in item_build.blocks.iter() {
+                    let items = block.items.clone().unwrap_or_default();
+                    let key = items
+                        .iter()
+                        .map(|i| (i.id.clone(), i.count))
+                        .collect::<Vec<_>>();
+                    if items.is_empty() || !seen.insert(key) {
+                        continue;
+                    }
+                    let label = if block.type_field.is_empty() {
+                        source.clone()
+                    } else {
+                        format!("{source}: {}", block.type_field)
+                    };
+                    build.blocks.push(Block {
+                        type_field: label,
+                        items: Some(items),
+                    });
+                }
+            }
+        }
+
+        if let Some(mut section) = base {
+            section.item_builds = build.into_iter().collect();
+            merged.push(section);
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builds::Item;
+
+    fn section(position: &str, blocks: &[(&str, &[&str])]) -> BuildSection {
+        let blocks = blocks
+            .iter()
+            .map(|(name, ids)| Block {
+                type_field: name.to_string(),
+                items: Some(
+                    ids.iter()
+                        .map(|id| Item {
+                            id: id.to_string(),
+                            count: 1,
+                        })
+                        .collect(),
+                ),
+            })
+            .collect();
+        BuildSection {
+            alias: String::from("Ahri"),
+            position: position.to_string(),
+            item_builds: vec![ItemBuild {
+                blocks,
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn merges_by_position_in_priority_order() {
+        let sources = vec![
+            (
+                String::from("op.gg"),
+                vec![section(
+                    "mid",
+                    &[("Starter", &["1056", "2003"]), ("Core", &["6655", "3020"])],
+                )],
+            ),
+            (
+                String::from("u.gg"),
+                vec![
+                    section("support", &[("Core", &["3853"])]),
+                    section(
+                        "mid",
+                        &[("Starter", &["1056", "2003"]), ("Core", &["4645"])],
+                    ),
+                ],
+            ),
+        ];
+
+        let merged = merge_sections(&sources);
+        assert_eq!(
+            merged
+                .iter()
+                .map(|s| s.position.as_str())
+                .collect::<Vec<_>>(),
+            ["mid", "support"]
+        );
+        let mid = &merged[0].item_builds;
+        assert_eq!(mid.len(), 1);
+        assert_eq!(mid[0].title, "ChampR mid");
+        assert_eq!(
+            mid[0]
+                .blocks
+                .iter()
+                .map(|b| b.type_field.as_str())
+                .collect::<Vec<_>>(),
+            ["op.gg: Starter", "op.gg: Core", "u.gg: Core"]
+        );
+        assert_eq!(merged[1].item_builds[0].blocks[0].type_field, "u.gg: Core");
+    }
+
+    #[test]
+    fn merges_positions_spelled_differently() {
+        let sources = vec![
+            (
+                String::from("op.gg"),
+                vec![
+                    section("middle", &[("Core", &["6655"])]),
+                    section("bottom", &[("Core", &["6672"])]),
+                ],
+            ),
+            (
+                String::from("u.gg"),
+                vec![
+                    section("mid", &[("Core", &["4645"])]),
+                    section("adc", &[("Core", &["3031"])]),
+                ],
+            ),
+        ];
+
+        let merged = merge_sections(&sources);
+        assert_eq!(
+            merged
+                .iter()
+                .map(|s| s.position.as_str())
+                .collect::<Vec<_>>(),
+            ["mid", "adc"]
+        );
+        assert_eq!(merged[0].item_builds[0].title, "ChampR mid");
+        assert_eq!(
+            merged[0].item_builds[0]
+                .blocks
+                .iter()
+                .map(|b| b.type_field.as_str())
+                .collect::<Vec<_>>(),
+            ["op.gg: Core", "u.gg: Core"]
+        );
+        assert_eq!(merged[1].item_builds[0].blocks.len(), 2);
+    }
+}
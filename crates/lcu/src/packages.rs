@@ -0,0 +1,286 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use kv_log_macro::info;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cancel::CancellationToken,
+    endpoints::Mirrors,
+    fetch_error::FetchError,
+    progress::{ApplyStage, Progress},
+    web::{self, Package},
+};
+
+pub const NPM_DIR: &str = ".npm";
+const INDEX_FILE: &str = "index.json";
+
+lazy_static! {
+    // sources are installed concurrently, so index updates are serialized
+    static ref INDEX_LOCK: Mutex<()> = Mutex::new(());
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InstalledPackage {
+    pub version: String,
+    /// The game patch its builds are for, empty for packages installed before
+    /// it was recorded.
+    #[serde(default)]
+    pub source_version: String,
+    pub integrity: Option<String>,
+    pub tarball: String,
+    pub installed_at: u64,
+}
+
+/// Records which version of each `@champ-r/<source>` package sits in `.npm/<source>`.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PackageIndex {
+    pub packages: HashMap<String, InstalledPackage>,
+}
+
+impl PackageIndex {
+    pub fn load(npm_dir: &Path) -> Self {
+        fs::read_to_string(npm_dir.join(INDEX_FILE))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, npm_dir: &Path) -> anyhow::Result<()> {
+        fs::create_dir_all(npm_dir)?;
+        let path = npm_dir.join(INDEX_FILE);
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    pub fn get(&self, source: &str) -> Option<&InstalledPackage> {
+        self.packages.get(source)
+    }
+
+    /// Updates a single entry, re-reading the index so concurrent installs do not clobber each other.
+    pub fn record(npm_dir: &Path, source: &str, pkg: &Package) -> anyhow::Result<()> {
+        let _guard = INDEX_LOCK.lock().unwrap();
+        let mut index = Self::load(npm_dir);
+        index.packages.insert(
+            source.to_string(),
+            InstalledPackage {
+                version: pkg.version.clone(),
+                source_version: pkg.source_version.clone(),
+                integrity: pkg.dist.integrity.clone(),
+                tarball: pkg.dist.tarball.clone(),
+                installed_at: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs(),
+            },
+        );
+        index.save(npm_dir)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PackageStatus {
+    NotInstalled { latest: String },
+    UpToDate { version: String },
+    UpdateAvailable { installed: String, latest: String },
+}
+
+impl PackageStatus {
+    pub fn of(installed: Option<&InstalledPackage>, latest: &Package) -> Self {
+        match installed {
+            None => PackageStatus::NotInstalled {
+                latest: latest.version.clone(),
+            },
+            Some(p) if is_same_package(p, latest) => PackageStatus::UpToDate {
+                version: p.version.clone(),
+            },
+            Some(p) => PackageStatus::UpdateAvailable {
+                installed: p.version.clone(),
+                latest: latest.version.clone(),
+            },
+        }
+    }
+
+    pub fn needs_download(&self) -> bool {
+        !matches!(self, PackageStatus::UpToDate { .. })
+    }
+}
+
+fn is_same_package(installed: &InstalledPackage, latest: &Package) -> bool {
+    installed.version == latest.version
+        && match (&installed.integrity, &latest.dist.integrity) {
+            (Some(a), Some(b)) => a == b,
+            _ => true,
+        }
+}
+
+pub fn package_dir(npm_dir: &Path, source: &str) -> PathBuf {
+    npm_dir.join(source)
+}
+
+/// Where the build files of an installed package live.
+pub fn builds_dir(npm_dir: &Path, source: &str) -> PathBuf {
+    package_dir(npm_dir, source).join("package")
+}
+
+/// Compares the installed package of `source` with the latest one on npm.
+pub async fn check_status(
+    mirrors: &Mirrors,
+    npm_dir: &Path,
+    source: &str,
+) -> Result<PackageStatus, FetchError> {
+    check(mirrors, npm_dir, source)
+        .await
+        .map(|(status, _)| status)
+}
+
+/// Like `check_status`, also returning the installed package, whose
+/// `source_version` tells which game patch the builds on disk are for.
+pub async fn check(
+    mirrors: &Mirrors,
+    npm_dir: &Path,
+    source: &str,
+) -> Result<(PackageStatus, Option<InstalledPackage>), FetchError> {
+    let latest = web::fetch_package(mirrors, source).await?;
+    let index = PackageIndex::load(npm_dir);
+    let installed = index
+        .get(source)
+        .filter(|_| builds_dir(npm_dir, source).exists());
+    Ok((PackageStatus::of(installed, &latest), installed.cloned()))
+}
+
+/// Makes sure the latest package for `source` is extracted under `npm_dir`,
+/// downloading it only when the installed version differs.
+/// Returns whether anything was downloaded.
+pub async fn sync(
+    mirrors: &Mirrors,
+    npm_dir: &Path,
+    source: &str,
+    progress: &Progress,
+    cancel: &CancellationToken,
+) -> anyhow::Result<bool> {
+    progress.emit(ApplyStage::Resolving);
+    let latest = web::fetch_package(mirrors, source)
+        .await
+        .with_context(|| format!("resolve npm package for {source}"))?;
+
+    let index = PackageIndex::load(npm_dir);
+    let installed = index
+        .get(source)
+        .filter(|_| builds_dir(npm_dir, source).exists());
+    let status = PackageStatus::of(installed, &latest);
+    if !status.needs_download() {
+        info!(
+            "{} is up to date ({}), skip downloading",
+            source, &latest.version
+        );
+        return Ok(false);
+    }
+
+    info!(
+        "downloading {} {} from {}",
+        source, &latest.version, &latest.dist.tarball
+    );
+    // the outdated `package` folder is only replaced once the new one is complete
+    let output_dir = package_dir(npm_dir, source);
+    fs::create_dir_all(&output_dir)
+        .with_context(|| format!("create output dir {}", output_dir.display()))?;
+
+    web::download_and_extract_tgz(
+        &latest.dist,
+        &output_dir.to_string_lossy(),
+        progress,
+        cancel,
+    )
+    .await
+    .with_context(|| format!("download {}", &latest.dist.tarball))?;
+    PackageIndex::record(npm_dir, source, &latest)?;
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{self, Response};
+    use std::sync::{Arc, Mutex};
+
+    #[tokio::test]
+    async fn skips_download_when_version_is_unchanged() {
+        let version = Arc::new(Mutex::new(String::from("1.0.0")));
+        let tgz = test_server::make_tgz(&[("package/Ahri.json", b"[]")]);
+        let v = version.clone();
+        let server = test_server::serve(move |req| {
+            if req.path.ends_with("/latest") {
+                let host = req.header("host").cloned().unwrap_or_default();
+                Response::ok(test_server::package_json(
+                    &v.lock().unwrap(),
+                    &format!("http://{host}/op.gg.tgz"),
+                    &tgz,
+                    2,
+                ))
+            } else {
+                Response::ok(tgz.clone())
+            }
+        })
+        .await;
+        let mirrors = Mirrors::new(&[server.url("")]);
+        let npm_dir = test_server::temp_dir("npm");
+
+        assert!(sync(
+            &mirrors,
+            &npm_dir,
+            "op.gg",
+            &Progress::default(),
+            &CancellationToken::new()
+        )
+        .await
+        .unwrap());
+        assert!(builds_dir(&npm_dir, "op.gg").join("Ahri.json").exists());
+        assert!(!sync(
+            &mirrors,
+            &npm_dir,
+            "op.gg",
+            &Progress::default(),
+            &CancellationToken::new()
+        )
+        .await
+        .unwrap());
+        assert_eq!(server.hits("/op.gg.tgz"), 1);
+
+        *version.lock().unwrap() = String::from("1.0.1");
+        assert_eq!(
+            check_status(&mirrors, &npm_dir, "op.gg").await.unwrap(),
+            PackageStatus::UpdateAvailable {
+                installed: String::from("1.0.0"),
+                latest: String::from("1.0.1"),
+            }
+        );
+        let (_, installed) = check(&mirrors, &npm_dir, "op.gg").await.unwrap();
+        assert_eq!(installed.unwrap().version, "1.0.0");
+        assert!(sync(
+            &mirrors,
+            &npm_dir,
+            "op.gg",
+            &Progress::default(),
+            &CancellationToken::new()
+        )
+        .await
+        .unwrap());
+        assert_eq!(server.hits("/op.gg.tgz"), 2);
+        assert_eq!(
+            PackageIndex::load(&npm_dir).get("op.gg").unwrap().version,
+            "1.0.1"
+        );
+    }
+}
@@ -0,0 +1,121 @@
+use std::fmt;
+
+/// Riot ships roughly this many patches a season, used to compare across seasons.
+const PATCHES_PER_SEASON: u32 = 24;
+
+/// A game patch such as `14.2`, ignoring the build and hotfix numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Patch {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl Patch {
+    /// Reads the patch from versions like `14.2`, `14.2.1` or the client's `14.2.556.5763`.
+    pub fn parse(version: &str) -> Option<Patch> {
+        let mut parts = version.trim().trim_start_matches('v').split('.');
+        let major = parts.next()?.parse().ok()?;
+        let minor = parts
+            .next()?
+            .chars()
+            .take_while(|c| c.is_ascii_digit())
+            .collect::<String>()
+            .parse()
+            .ok()?;
+        Some(Patch { major, minor })
+    }
+
+    /// How many patches `self` is behind `client`, zero when it is current or newer.
+    pub fn behind(&self, client: &Patch) -> u32 {
+        if self >= client {
+            return 0;
+        }
+        let seasons = client.major - self.major;
+        (seasons * PATCHES_PER_SEASON + client.minor).saturating_sub(self.minor)
+    }
+}
+
+impl fmt::Display for Patch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// How the data of a source compares with the running client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Staleness {
+    Current,
+    Behind {
+        data: Patch,
+        client: Patch,
+        patches: u32,
+    },
+    /// Either the client is not running or the data carries no usable version.
+    Unknown,
+}
+
+impl Staleness {
+    pub fn of(data_version: &str, client: Option<Patch>) -> Self {
+        match (Patch::parse(data_version), client) {
+            (Some(data), Some(client)) => match data.behind(&client) {
+                0 => Staleness::Current,
+                patches => Staleness::Behind {
+                    data,
+                    client,
+                    patches,
+                },
+            },
+            _ => Staleness::Unknown,
+        }
+    }
+
+    pub fn is_outdated(&self) -> bool {
+        matches!(self, Staleness::Behind { .. })
+    }
+
+    /// Applying is worth a warning once the data is more than one patch old.
+    pub fn needs_warning(&self) -> bool {
+        matches!(self, Staleness::Behind { patches, .. } if *patches > 1)
+    }
+
+    pub fn describe(&self) -> Option<String> {
+        match self {
+            Staleness::Behind {
+                data,
+                client,
+                patches,
+            } => Some(format!(
+                "Data is for patch {data}, the client runs {client} ({patches} patch{} behind)",
+                if *patches == 1 { "" } else { "es" }
+            )),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compares_data_with_the_client_patch() {
+        let client = Patch::parse("14.3.559.2402");
+        assert_eq!(
+            client,
+            Some(Patch {
+                major: 14,
+                minor: 3
+            })
+        );
+        assert_eq!(Patch::parse("v14.2.1"), Patch::parse("14.2"));
+        assert_eq!(Patch::parse("unknown"), None);
+
+        assert_eq!(Staleness::of("14.3.1", client), Staleness::Current);
+        assert_eq!(Staleness::of("14.4", client), Staleness::Current);
+        assert!(!Staleness::of("14.2", client).needs_warning());
+        assert!(Staleness::of("14.2", client).is_outdated());
+        assert!(Staleness::of("14.1", client).needs_warning());
+        assert!(Staleness::of("13.24", client).needs_warning());
+        assert_eq!(Staleness::of("14.1", None), Staleness::Unknown);
+    }
+}
@@ -0,0 +1,161 @@
+//! The champions and positions a player cares about, so only their builds are
+//! written instead of every champion in every position.
+
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{api, builds::BuildSection, lcu_error::LcuError};
+
+/// Which champions get item sets.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PoolMode {
+    #[default]
+    All,
+    /// The champions listed in the settings.
+    Listed,
+    /// The champions the summoner owns or rents.
+    Owned,
+}
+
+/// Lane positions; sections for anything else, such as ARAM, are never
+/// filtered by position.
+pub const LANES: &[&str] = &["top", "jungle", "mid", "adc", "support"];
+
+/// Maps the position names of the different sources and the client onto `LANES`.
+pub fn normalize_position(position: &str) -> String {
+    let position = position.trim().to_lowercase();
+    match position.as_str() {
+        "middle" => String::from("mid"),
+        "bottom" | "bot" | "carry" => String::from("adc"),
+        "utility" | "supp" => String::from("support"),
+        "jng" | "jungler" => String::from("jungle"),
+        _ => position,
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChampionPool {
+    /// Lowercase aliases, `None` for every champion.
+    champions: Option<HashSet<String>>,
+    /// Normalized positions, empty for every position.
+    positions: Vec<String>,
+}
+
+impl ChampionPool {
+    /// Every champion in every position, i.e. no filtering.
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    pub fn new<S: AsRef<str>>(champions: Option<&[S]>, positions: &[S]) -> Self {
+        Self {
+            champions: champions.map(|aliases| {
+                aliases
+                    .iter()
+                    .map(|alias| alias.as_ref().trim().to_lowercase())
+                    .filter(|alias| !alias.is_empty())
+                    .collect()
+            }),
+            positions: positions
+                .iter()
+                .map(|p| normalize_position(p.as_ref()))
+                .filter(|p| !p.is_empty())
+                .collect(),
+        }
+    }
+
+    pub fn is_all(&self) -> bool {
+        self.champions.is_none() && self.positions.is_empty()
+    }
+
+    pub fn has_champion(&self, alias: &str) -> bool {
+        self.champions
+            .as_ref()
+            .is_none_or(|champions| champions.contains(&alias.to_lowercase()))
+    }
+
+    pub fn has_position(&self, position: &str) -> bool {
+        let position = normalize_position(position);
+        self.positions.is_empty()
+            || !LANES.contains(&position.as_str())
+            || self.positions.contains(&position)
+    }
+
+    pub fn matches(&self, section: &BuildSection) -> bool {
+        self.has_champion(&section.alias) && self.has_position(&section.position)
+    }
+
+    /// Keeps only the sections of champions and positions in the pool.
+    pub fn filter_sections(&self, sections: &mut Vec<BuildSection>) {
+        sections.retain(|section| self.matches(section));
+    }
+}
+
+/// Aliases of the champions the current summoner owns or rents.
+pub async fn owned_champions(endpoint: &String) -> Result<Vec<String>, LcuError> {
+    let summoner = api::get_current_summoner(endpoint).await?;
+    let champions = api::list_available_champions(endpoint, summoner.summoner_id).await?;
+    Ok(champions
+        .into_iter()
+        .filter(|c| c.id > 0 && (c.ownership.owned || c.ownership.rental.rented))
+        .map(|c| c.alias)
+        .collect())
+}
+
+/// The pool for `mode`, asking the LCU at `endpoint` for the owned champions.
+/// Positions are only limited when `positions` is not empty.
+pub async fn resolve(
+    mode: PoolMode,
+    listed: &[String],
+    positions: &[String],
+    endpoint: &String,
+) -> Result<ChampionPool, LcuError> {
+    Ok(match mode {
+        PoolMode::All => ChampionPool::new(None, positions),
+        PoolMode::Listed => ChampionPool::new(Some(listed), positions),
+        PoolMode::Owned => {
+            let owned = owned_champions(endpoint).await?;
+            ChampionPool::new(Some(&owned), positions)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn section(alias: &str, position: &str) -> BuildSection {
+        BuildSection {
+            alias: alias.to_string(),
+            position: position.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn keeps_pool_champions_in_preferred_positions() {
+        let pool = ChampionPool::new(Some(&["Ahri", "MonkeyKing"]), &["middle", "Jungle"]);
+        let mut sections = vec![
+            section("Ahri", "mid"),
+            section("Ahri", "support"),
+            section("monkeyking", "jungle"),
+            section("MonkeyKing", "aram"),
+            section("Zed", "mid"),
+        ];
+        pool.filter_sections(&mut sections);
+        assert_eq!(
+            sections
+                .iter()
+                .map(|s| format!("{} {}", s.alias, s.position))
+                .collect::<Vec<_>>(),
+            ["Ahri mid", "monkeyking jungle", "MonkeyKing aram"]
+        );
+
+        let positions_only = ChampionPool::new(None, &["utility"]);
+        assert!(positions_only.matches(&section("Zed", "support")));
+        assert!(!positions_only.matches(&section("Zed", "top")));
+        assert!(ChampionPool::all().is_all());
+    }
+}
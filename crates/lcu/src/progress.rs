@@ -0,0 +1,91 @@
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+
+/// Where applying the builds of one source currently is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApplyStage {
+    Resolving,
+    Downloading { received: u64, total: Option<u64> },
+    Extracting,
+    Planning,
+    Writing { done: usize, total: usize },
+    Done,
+    Failed(String),
+}
+
+impl ApplyStage {
+    /// Overall completion between 0 and 1, when it can be told.
+    pub fn fraction(&self) -> Option<f32> {
+        match self {
+            ApplyStage::Resolving => Some(0.),
+            ApplyStage::Downloading {
+                received,
+                total: Some(total),
+            } if *total > 0 => Some(0.5 * (*received as f32 / *total as f32).min(1.)),
+            ApplyStage::Downloading { .. } => None,
+            ApplyStage::Extracting | ApplyStage::Planning => Some(0.5),
+            ApplyStage::Writing { done, total } if *total > 0 => {
+                Some(0.5 + 0.5 * (*done as f32 / *total as f32))
+            }
+            ApplyStage::Writing { .. } => Some(0.5),
+            ApplyStage::Done | ApplyStage::Failed(_) => Some(1.),
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            ApplyStage::Resolving => String::from("Resolving package"),
+            ApplyStage::Downloading { received, total } => match total {
+                Some(total) => format!("Downloading {} / {} KB", received / 1024, total / 1024),
+                None => format!("Downloading {} KB", received / 1024),
+            },
+            ApplyStage::Extracting => String::from("Extracting"),
+            ApplyStage::Planning => String::from("Comparing with the game folder"),
+            ApplyStage::Writing { done, total } => format!("{done} of {total} files written"),
+            ApplyStage::Done => String::from("Done"),
+            ApplyStage::Failed(err) => format!("Failed: {err}"),
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(self, ApplyStage::Done | ApplyStage::Failed(_))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProgressEvent {
+    pub source: String,
+    pub stage: ApplyStage,
+}
+
+pub type ProgressSender = UnboundedSender<ProgressEvent>;
+pub type ProgressReceiver = UnboundedReceiver<ProgressEvent>;
+
+pub fn channel() -> (ProgressSender, ProgressReceiver) {
+    tokio::sync::mpsc::unbounded_channel()
+}
+
+/// Reports the stages of one source. Does nothing without a sender.
+#[derive(Debug, Clone, Default)]
+pub struct Progress {
+    source: String,
+    tx: Option<ProgressSender>,
+}
+
+impl Progress {
+    pub fn new(source: &str, tx: Option<ProgressSender>) -> Self {
+        Self {
+            source: source.to_string(),
+            tx,
+        }
+    }
+
+    pub fn emit(&self, stage: ApplyStage) {
+        if let Some(tx) = &self.tx {
+            // the receiver going away only means nobody is watching anymore
+            let _ = tx.send(ProgressEvent {
+                source: self.source.clone(),
+                stage,
+            });
+        }
+    }
+}
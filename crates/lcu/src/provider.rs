@@ -0,0 +1,296 @@
+//! Where builds come from. The service and the npm packages are built in,
+//! other providers (a local folder, an internal builds server, ...) can be
+//! registered next to them without touching `web.rs`.
+
+use std::{
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use futures::StreamExt;
+use kv_log_macro::{info, warn};
+use lazy_static::lazy_static;
+
+use crate::{
+    builds::BuildSection,
+    cache,
+    cancel::{self, CancellationToken},
+    champions,
+    endpoints::{self, Mirrors},
+    fetch_error::FetchError,
+    local_source::LOCAL_SOURCE,
+    packages,
+    progress::{ApplyStage, Progress},
+    source::SourceItem,
+    web,
+};
+
+/// A bundle holds the builds of every champion of a source, one entry per champion.
+pub type Bundle = Vec<Vec<BuildSection>>;
+
+#[async_trait]
+pub trait BuildProvider: Send + Sync {
+    /// Identifies the provider, e.g. `npm` or `local`.
+    fn id(&self) -> &str;
+
+    /// Whether this provider serves `source`. Providers are asked from the most
+    /// recently registered one back, so a catch-all provider should be
+    /// registered first.
+    fn provides(&self, _source: &str) -> bool {
+        true
+    }
+
+    async fn list_sources(&self) -> anyhow::Result<Vec<SourceItem>>;
+
+    async fn list_builds(
+        &self,
+        source: &str,
+        champion_alias: &str,
+    ) -> anyhow::Result<Vec<BuildSection>>;
+
+    async fn fetch_bundle(
+        &self,
+        source: &str,
+        progress: &Progress,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<Bundle>;
+}
+
+/// The providers builds are looked up in, each source served by the most
+/// recently registered provider that knows it. The npm packages are built in.
+pub struct Registry {
+    providers: RwLock<Vec<Arc<dyn BuildProvider>>>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self {
+            providers: RwLock::new(vec![Arc::new(NpmProvider::default())]),
+        }
+    }
+}
+
+impl Registry {
+    /// Adds a provider, replacing any registered with the same id.
+    /// It takes precedence over the ones registered before it.
+    pub fn register(&self, provider: Arc<dyn BuildProvider>) {
+        let mut providers = self.providers.write().unwrap();
+        providers.retain(|p| p.id() != provider.id());
+        info!("registered build provider {}", provider.id());
+        providers.push(provider);
+    }
+
+    pub fn unregister(&self, id: &str) {
+        self.providers.write().unwrap().retain(|p| p.id() != id);
+    }
+
+    pub fn providers(&self) -> Vec<Arc<dyn BuildProvider>> {
+        self.providers.read().unwrap().clone()
+    }
+
+    /// The most recently registered provider serving `source`.
+    pub fn for_source(&self, source: &str) -> anyhow::Result<Arc<dyn BuildProvider>> {
+        self.providers()
+            .into_iter()
+            .rev()
+            .find(|p| p.provides(source))
+            .ok_or_else(|| anyhow::anyhow!("no build provider serves {source}"))
+    }
+
+    /// Sources of all providers, later registered ones first. A failing provider
+    /// is skipped unless every one of them fails.
+    pub async fn list_sources(&self) -> anyhow::Result<Vec<SourceItem>> {
+        let providers = self.providers();
+        let results =
+            futures::future::join_all(providers.iter().rev().map(|p| p.list_sources())).await;
+
+        let mut sources: Vec<SourceItem> = vec![];
+        let mut last_err = None;
+        for (provider, result) in providers.iter().rev().zip(results) {
+            match result {
+                Ok(list) => {
+                    for item in list {
+                        if !sources.iter().any(|s| s.value == item.value) {
+                            sources.push(item);
+                        }
+                    }
+                }
+                Err(err) => {
+                    warn!("list sources of {}: {:#}", provider.id(), err);
+                    last_err = Some(err);
+                }
+            }
+        }
+
+        match last_err {
+            Some(err) if sources.is_empty() => Err(err),
+            _ => Ok(sources),
+        }
+    }
+
+    pub async fn list_builds(
+        &self,
+        source: &str,
+        champion_alias: &str,
+    ) -> anyhow::Result<Vec<BuildSection>> {
+        self.for_source(source)?
+            .list_builds(source, champion_alias)
+            .await
+    }
+}
+
+lazy_static! {
+    static ref REGISTRY: Registry = Registry::default();
+}
+
+/// Adds a provider to the registry of the app, see `Registry::register`.
+pub fn register(provider: Arc<dyn BuildProvider>) {
+    REGISTRY.register(provider)
+}
+
+pub fn unregister(id: &str) {
+    REGISTRY.unregister(id)
+}
+
+pub fn providers() -> Vec<Arc<dyn BuildProvider>> {
+    REGISTRY.providers()
+}
+
+pub fn for_source(source: &str) -> anyhow::Result<Arc<dyn BuildProvider>> {
+    REGISTRY.for_source(source)
+}
+
+pub async fn list_sources() -> anyhow::Result<Vec<SourceItem>> {
+    REGISTRY.list_sources().await
+}
+
+pub async fn list_builds(source: &str, champion_alias: &str) -> anyhow::Result<Vec<BuildSection>> {
+    REGISTRY.list_builds(source, champion_alias).await
+}
+
+/// The `c.lbj.moe` compatible service, one request per champion.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ServiceProvider;
+
+/// How many champions are requested at once when building a bundle from the service.
+const SERVICE_CONCURRENCY: usize = 10;
+
+#[async_trait]
+impl BuildProvider for ServiceProvider {
+    fn id(&self) -> &str {
+        "service"
+    }
+
+    async fn list_sources(&self) -> anyhow::Result<Vec<SourceItem>> {
+        Ok(web::fetch_sources().await?)
+    }
+
+    async fn list_builds(
+        &self,
+        source: &str,
+        champion_alias: &str,
+    ) -> anyhow::Result<Vec<BuildSection>> {
+        Ok(web::list_builds_by_alias(&source.to_string(), &champion_alias.to_string()).await?)
+    }
+
+    async fn fetch_bundle(
+        &self,
+        source: &str,
+        progress: &Progress,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<Bundle> {
+        progress.emit(ApplyStage::Resolving);
+        let aliases = champions::load()
+            .await?
+            .iter()
+            .map(|c| c.alias.clone())
+            .collect::<Vec<_>>();
+
+        let tasks = aliases.into_iter().map(|alias| async move {
+            if cancel.is_cancelled() {
+                return None;
+            }
+            match self.list_builds(source, &alias).await {
+                Ok(sections) if !sections.is_empty() => Some(sections),
+                Ok(_) => None,
+                Err(err) => {
+                    warn!("list builds of {} from {}: {:#}", &alias, source, err);
+                    None
+                }
+            }
+        });
+        let bundle = futures::stream::iter(tasks)
+            .buffer_unordered(SERVICE_CONCURRENCY)
+            .filter_map(|sections| async move { sections })
+            .collect::<Bundle>()
+            .await;
+        cancel::check(cancel)?;
+
+        Ok(bundle)
+    }
+}
+
+/// The `@champ-r/<source>` npm packages. Sources and single champions are looked
+/// up on the service, whole sources are installed from the package tarballs.
+#[derive(Debug, Clone)]
+pub struct NpmProvider {
+    pub mirrors: &'static Mirrors,
+    pub npm_dir: PathBuf,
+}
+
+impl Default for NpmProvider {
+    fn default() -> Self {
+        Self {
+            mirrors: endpoints::npm(),
+            npm_dir: PathBuf::from(packages::NPM_DIR),
+        }
+    }
+}
+
+#[async_trait]
+impl BuildProvider for NpmProvider {
+    fn id(&self) -> &str {
+        "npm"
+    }
+
+    /// Every source but the local folder, which has no package.
+    fn provides(&self, source: &str) -> bool {
+        source != LOCAL_SOURCE
+    }
+
+    async fn list_sources(&self) -> anyhow::Result<Vec<SourceItem>> {
+        ServiceProvider.list_sources().await
+    }
+
+    async fn list_builds(
+        &self,
+        source: &str,
+        champion_alias: &str,
+    ) -> anyhow::Result<Vec<BuildSection>> {
+        ServiceProvider.list_builds(source, champion_alias).await
+    }
+
+    async fn fetch_bundle(
+        &self,
+        source: &str,
+        progress: &Progress,
+        cancel: &CancellationToken,
+    ) -> anyhow::Result<Bundle> {
+        let dest_folder = packages::builds_dir(&self.npm_dir, source);
+
+        if cache::is_offline() {
+            if !dest_folder.exists() {
+                return Err(FetchError::Offline(format!("the {source} package")).into());
+            }
+            info!("offline, using cached package for {}", source);
+        } else {
+            packages::sync(self.mirrors, &self.npm_dir, source, progress, cancel).await?;
+        }
+
+        web::read_from_local_folder(&dest_folder.to_string_lossy())
+            .await
+            .with_context(|| format!("read builds of {source}"))
+    }
+}
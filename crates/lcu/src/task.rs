@@ -4,10 +4,11 @@ use std::{
     time::Duration,
 };
 
+use kv_log_macro::warn;
+
 use crate::{
-    api,
+    api, champions,
     cmd::{self, CommandLineOutput},
-    constants::ALL_CHAMPION_IDS,
 };
 
 pub async fn watch_auth_and_champion(
@@ -47,7 +48,7 @@ pub async fn watch_auth_and_champion(
             } else {
                 if enabled_random_mode {
                     if champion_id.read().unwrap().is_none() {
-                        *champion_id.write().unwrap() = Some(get_random_champion_id());
+                        *champion_id.write().unwrap() = get_random_champion_id().await;
                     }
                 } else {
                     *champion_id.write().unwrap() = None;
@@ -70,8 +71,12 @@ pub async fn watch_auth_and_champion(
     }
 }
 
-fn get_random_champion_id() -> i64 {
-    use rand::seq::SliceRandom;
-
-    *ALL_CHAMPION_IDS.choose(&mut rand::thread_rng()).unwrap()
+async fn get_random_champion_id() -> Option<i64> {
+    match champions::get().await {
+        Ok(registry) => registry.random().map(|c| c.id),
+        Err(err) => {
+            warn!("load champions for random mode: {}", err);
+            None
+        }
+    }
 }
@@ -0,0 +1,210 @@
+//! A tiny HTTP/1.1 stand-in for tests, so they never depend on `c.lbj.moe`,
+//! npm mirrors or GitHub being reachable.
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpListener,
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct Request {
+    pub path: String,
+    pub headers: HashMap<String, String>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&String> {
+        self.headers.get(&name.to_lowercase())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    /// Write the body in pieces of this size with a pause in between.
+    pub chunk_size: Option<usize>,
+}
+
+impl Response {
+    pub fn ok(body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status: 200,
+            headers: vec![],
+            body: body.into(),
+            chunk_size: None,
+        }
+    }
+
+    pub fn status(status: u16) -> Self {
+        Self {
+            status,
+            headers: vec![],
+            body: vec![],
+            chunk_size: None,
+        }
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn in_chunks(mut self, size: usize) -> Self {
+        self.chunk_size = Some(size);
+        self
+    }
+}
+
+type Handler = Arc<dyn Fn(&Request) -> Response + Send + Sync>;
+
+pub struct TestServer {
+    pub addr: SocketAddr,
+    pub requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl TestServer {
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    pub fn hits(&self, path: &str) -> usize {
+        self.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|r| r.path == path)
+            .count()
+    }
+}
+
+pub async fn serve<F>(handler: F) -> TestServer
+where
+    F: Fn(&Request) -> Response + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handler: Handler = Arc::new(handler);
+    let requests = Arc::new(Mutex::new(vec![]));
+    let requests_task = requests.clone();
+
+    tokio::spawn(async move {
+        loop {
+            let Ok((stream, _)) = listener.accept().await else {
+                break;
+            };
+            let handler = handler.clone();
+            let requests = requests_task.clone();
+
+            tokio::spawn(async move {
+                let (read, mut write) = stream.into_split();
+                let mut reader = BufReader::new(read);
+                let mut line = String::new();
+                if reader.read_line(&mut line).await.is_err() {
+                    return;
+                }
+                let mut req = Request {
+                    path: line
+                        .split_whitespace()
+                        .nth(1)
+                        .unwrap_or_default()
+                        .to_string(),
+                    ..Default::default()
+                };
+                loop {
+                    let mut header = String::new();
+                    if reader.read_line(&mut header).await.unwrap_or(0) == 0 {
+                        break;
+                    }
+                    let header = header.trim_end();
+                    if header.is_empty() {
+                        break;
+                    }
+                    if let Some((k, v)) = header.split_once(':') {
+                        req.headers
+                            .insert(k.trim().to_lowercase(), v.trim().to_string());
+                    }
+                }
+                requests.lock().unwrap().push(req.clone());
+
+                let resp = handler(&req);
+                let mut head = format!(
+                    "HTTP/1.1 {} X\r\ncontent-length: {}\r\nconnection: close\r\n",
+                    resp.status,
+                    resp.body.len()
+                );
+                for (k, v) in resp.headers.iter() {
+                    head.push_str(&format!("{k}: {v}\r\n"));
+                }
+                head.push_str("\r\n");
+                let _ = write.write_all(head.as_bytes()).await;
+                match resp.chunk_size {
+                    Some(size) => {
+                        for chunk in resp.body.chunks(size) {
+                            let _ = write.write_all(chunk).await;
+                            let _ = write.flush().await;
+                            tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+                        }
+                    }
+                    None => {
+                        let _ = write.write_all(&resp.body).await;
+                    }
+                }
+                let _ = write.shutdown().await;
+            });
+        }
+    });
+
+    TestServer { addr, requests }
+}
+
+/// A fresh, empty directory under the system temp dir.
+pub fn temp_dir(prefix: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("champr-{prefix}-{}", rand::random::<u64>()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Builds a gzipped tarball in memory, the way npm packs `@champ-r/*`.
+pub fn make_tgz(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let gz = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+    let mut builder = tar::Builder::new(gz);
+    for (path, content) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, path, *content).unwrap();
+    }
+    builder.into_inner().unwrap().finish().unwrap()
+}
+
+/// The npm `latest` document for a tarball, with a matching sha512 integrity.
+pub fn package_json(version: &str, tarball: &str, tgz: &[u8], unpacked_size: i64) -> String {
+    use base64::{engine::general_purpose, Engine as _};
+    use sha2::{Digest, Sha512};
+
+    let pkg = crate::web::Package {
+        name: String::from("@champ-r/test"),
+        version: version.to_string(),
+        dist: crate::web::Dist {
+            tarball: tarball.to_string(),
+            file_count: 1,
+            unpacked_size,
+            integrity: Some(format!(
+                "sha512-{}",
+                general_purpose::STANDARD.encode(Sha512::digest(tgz))
+            )),
+            shasum: None,
+        },
+        ..Default::default()
+    };
+    serde_json::to_string(&pkg).unwrap()
+}
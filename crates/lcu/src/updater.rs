@@ -0,0 +1,573 @@
+use std::{
+    cmp::Ordering,
+    env, fmt, fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use kv_log_macro::{info, warn};
+use reqwest::header::USER_AGENT;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    constants::VERSION,
+    fetch_error::{self, FetchError},
+    integrity::{Checksum, Verifier},
+    progress::{ApplyStage, Progress},
+    web::{LatestRelease, ReleaseAsset},
+};
+
+pub const RELEASES_API_ENV: &str = "CHAMPR_RELEASES_API";
+
+/// The GitHub repository ChampR releases are published to, as an API base url
+/// such as `https://api.github.com/repos/<owner>/<repo>`. `RELEASES_API_ENV`
+/// wins over `configured`; there is no default, so without either nothing is
+/// checked.
+pub fn releases_api(configured: &str) -> Option<String> {
+    env::var(RELEASES_API_ENV)
+        .ok()
+        .into_iter()
+        .chain([configured.to_string()])
+        .map(|url| url.trim().trim_end_matches('/').to_string())
+        .find(|url| !url.is_empty())
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UpdateChannel {
+    /// Only full releases.
+    #[default]
+    Stable,
+    /// Pre-releases such as `v2.0.2-b8` as well.
+    Nightly,
+}
+
+/// One dot separated part of a pre-release, e.g. `b8` in `2.0.2-b8`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PreIdent {
+    prefix: String,
+    number: Option<u64>,
+}
+
+impl Ord for PreIdent {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // like semver, purely numeric identifiers sort before alphanumeric ones,
+        // but `b10` sorts after `b9`
+        (!self.prefix.is_empty(), &self.prefix, self.number).cmp(&(
+            !other.prefix.is_empty(),
+            &other.prefix,
+            other.number,
+        ))
+    }
+}
+
+impl PartialOrd for PreIdent {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+    pre: Vec<PreIdent>,
+    raw_pre: String,
+}
+
+impl Version {
+    /// Parses tags such as `v2.0.2`, `2.1.0-b8` or `v2.1.0-nightly.3`.
+    pub fn parse(tag: &str) -> Option<Version> {
+        let tag = tag.trim().trim_start_matches('v');
+        let tag = tag.split('+').next()?;
+        let (core, pre) = match tag.split_once('-') {
+            Some((core, pre)) => (core, pre),
+            None => (tag, ""),
+        };
+
+        let mut nums = core.split('.').map(|n| n.parse::<u64>().ok());
+        let major = nums.next()??;
+        let minor = nums.next().unwrap_or(Some(0))?;
+        let patch = nums.next().unwrap_or(Some(0))?;
+        if nums.next().is_some() {
+            return None;
+        }
+
+        let idents = if pre.is_empty() {
+            vec![]
+        } else {
+            pre.split('.')
+                .map(|ident| {
+                    let split = ident
+                        .find(|c: char| c.is_ascii_digit())
+                        .unwrap_or(ident.len());
+                    let (prefix, digits) = ident.split_at(split);
+                    let number = if digits.is_empty() {
+                        None
+                    } else {
+                        Some(digits.parse().ok()?)
+                    };
+                    Some(PreIdent {
+                        prefix: prefix.to_string(),
+                        number,
+                    })
+                })
+                .collect::<Option<Vec<_>>>()?
+        };
+
+        Some(Version {
+            major,
+            minor,
+            patch,
+            pre: idents,
+            raw_pre: pre.to_string(),
+        })
+    }
+
+    pub fn is_prerelease(&self) -> bool {
+        !self.pre.is_empty()
+    }
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.major, self.minor, self.patch)
+            .cmp(&(other.major, other.minor, other.patch))
+            .then_with(|| match (self.pre.is_empty(), other.pre.is_empty()) {
+                (true, true) => Ordering::Equal,
+                // a release is newer than any of its pre-releases
+                (true, false) => Ordering::Greater,
+                (false, true) => Ordering::Less,
+                (false, false) => self.pre.cmp(&other.pre),
+            })
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "v{}.{}.{}", self.major, self.minor, self.patch)?;
+        if !self.raw_pre.is_empty() {
+            write!(f, "-{}", self.raw_pre)?;
+        }
+        Ok(())
+    }
+}
+
+pub async fn list_releases(api_base: &str) -> Result<Vec<LatestRelease>, FetchError> {
+    let resp = reqwest::Client::new()
+        .get(format!("{api_base}/releases?per_page=20"))
+        .header(USER_AGENT, "ChampR_rs")
+        .send()
+        .await?;
+    fetch_error::read_json(resp).await
+}
+
+/// The newest release on `channel` that is newer than `current`, if any.
+pub fn pick_update(
+    releases: Vec<LatestRelease>,
+    current: &str,
+    channel: UpdateChannel,
+) -> Option<LatestRelease> {
+    let current = Version::parse(current)?;
+    releases
+        .into_iter()
+        .filter(|r| !r.draft)
+        .filter_map(|r| Version::parse(&r.tag_name).map(|v| (v, r)))
+        .filter(|(v, r)| match channel {
+            UpdateChannel::Stable => !r.prerelease && !v.is_prerelease(),
+            UpdateChannel::Nightly => true,
+        })
+        .filter(|(v, _)| v > &current)
+        .max_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(_, r)| r)
+}
+
+/// Asks GitHub whether a newer ChampR than this one was released on `channel`.
+pub async fn check_for_update(
+    api_base: &str,
+    channel: UpdateChannel,
+) -> Result<Option<LatestRelease>, FetchError> {
+    let releases = list_releases(api_base).await?;
+    let update = pick_update(releases, VERSION, channel);
+    if let Some(release) = &update {
+        info!("update available: {} -> {}", VERSION, &release.tag_name);
+    }
+    Ok(update)
+}
+
+const CHECKSUM_SUFFIXES: [&str; 3] = [".sha256", ".sha256sum", ".sig"];
+
+/// Archives and installers, which cannot replace the running executable as is.
+const PACKAGE_SUFFIXES: [&str; 12] = [
+    ".zip", ".tar", ".gz", ".tgz", ".xz", ".7z", ".dmg", ".pkg", ".msi", ".deb", ".rpm", ".exe",
+];
+
+fn os_markers(os: &str) -> &'static [&'static str] {
+    match os {
+        "windows" => &["windows", "win64", "win32", ".exe"],
+        "macos" => &["macos", "darwin", "osx"],
+        "linux" => &["linux", ".appimage"],
+        _ => &[],
+    }
+}
+
+/// Whether an asset named `name` is an executable for `os` that can be
+/// installed by copying it: an `.exe` on Windows, an AppImage or a bare
+/// binary elsewhere.
+pub fn is_executable_asset(name: &str, os: &str) -> bool {
+    let name = name.to_lowercase();
+    if os == "windows" {
+        name.ends_with(".exe")
+    } else {
+        !CHECKSUM_SUFFIXES
+            .iter()
+            .chain(PACKAGE_SUFFIXES.iter())
+            .any(|s| name.ends_with(s))
+    }
+}
+
+fn arch_markers(arch: &str) -> &'static [&'static str] {
+    match arch {
+        "x86_64" => &["x86_64", "x64", "amd64"],
+        "aarch64" => &["aarch64", "arm64"],
+        "x86" => &["i686", "x86_32"],
+        _ => &[],
+    }
+}
+
+/// The executable built for `os` and `arch`, as named by `std::env::consts`.
+/// Assets that name no architecture are taken when none names ours; archives
+/// and installers are never taken.
+pub fn platform_asset<'a>(
+    release: &'a LatestRelease,
+    os: &str,
+    arch: &str,
+) -> Option<&'a ReleaseAsset> {
+    let others = ["x86_64", "aarch64", "x86"]
+        .into_iter()
+        .filter(|a| *a != arch)
+        .flat_map(arch_markers)
+        .filter(|m| !arch_markers(arch).contains(m))
+        .collect::<Vec<_>>();
+
+    release
+        .assets
+        .iter()
+        .filter(|asset| {
+            let name = asset.name.to_lowercase();
+            is_executable_asset(&name, os)
+                && os_markers(os).iter().any(|m| name.contains(m))
+                && !others.iter().any(|m| name.contains(*m))
+        })
+        .max_by_key(|asset| {
+            let name = asset.name.to_lowercase();
+            arch_markers(arch).iter().any(|m| name.contains(m))
+        })
+}
+
+/// The checksum of `asset`, from its GitHub digest or a `<name>.sha256` asset.
+async fn expected_checksum(
+    release: &LatestRelease,
+    asset: &ReleaseAsset,
+) -> Result<Checksum, FetchError> {
+    if let Some(checksum) = asset.digest.as_deref().and_then(Checksum::from_sha256) {
+        return Ok(checksum);
+    }
+
+    let sidecar = release.assets.iter().find(|a| {
+        CHECKSUM_SUFFIXES[..2]
+            .iter()
+            .any(|s| a.name == format!("{}{s}", asset.name))
+    });
+    if let Some(sidecar) = sidecar {
+        let resp = reqwest::get(&sidecar.browser_download_url).await?;
+        let text = fetch_error::read_text(resp).await?;
+        if let Some(checksum) = Checksum::from_sha256(&text) {
+            return Ok(checksum);
+        }
+    }
+
+    Err(FetchError::Integrity {
+        url: asset.browser_download_url.clone(),
+        message: String::from("the release published no sha256 for this asset"),
+    })
+}
+
+/// Downloads the asset of `release` for this platform into `dir` and verifies it.
+pub async fn download_update(
+    release: &LatestRelease,
+    dir: &Path,
+    progress: &Progress,
+) -> Result<PathBuf, FetchError> {
+    let asset = platform_asset(release, env::consts::OS, env::consts::ARCH).ok_or_else(|| {
+        FetchError::NotFound(format!(
+            "{} build in release {}",
+            env::consts::OS,
+            &release.tag_name
+        ))
+    })?;
+    let mut verifier = Verifier::new(expected_checksum(release, asset).await?);
+
+    fs::create_dir_all(dir)?;
+    let partial = dir.join(format!("{}.download", &asset.name));
+    let download = async {
+        let url = &asset.browser_download_url;
+        let mut resp = reqwest::Client::new()
+            .get(url)
+            .header(USER_AGENT, "ChampR_rs")
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            return Err(FetchError::status(url, resp.status().as_u16(), ""));
+        }
+
+        let total = resp
+            .content_length()
+            .or(Some(asset.size).filter(|s| *s > 0));
+        let mut received = 0u64;
+        let mut bytes = Vec::with_capacity(total.unwrap_or_default() as usize);
+        progress.emit(ApplyStage::Downloading { received, total });
+        while let Some(chunk) = resp.chunk().await? {
+            verifier.update(&chunk);
+            bytes.extend_from_slice(&chunk);
+            received += chunk.len() as u64;
+            progress.emit(ApplyStage::Downloading { received, total });
+        }
+        verifier.finish(url)?;
+        fs::write(&partial, &bytes)?;
+        Ok(())
+    };
+    if let Err(err) = download.await {
+        let _ = fs::remove_file(&partial);
+        return Err(err);
+    }
+
+    let binary = dir.join(&asset.name);
+    fs::rename(&partial, &binary)?;
+    info!("downloaded {} for {}", &asset.name, &release.tag_name);
+    Ok(binary)
+}
+
+fn sibling(target: &Path, suffix: &str) -> PathBuf {
+    let mut name = target.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    target.with_file_name(name)
+}
+
+/// Where the previous executable is kept after an update.
+pub fn backup_path(target: &Path) -> PathBuf {
+    sibling(target, ".bak")
+}
+
+/// Moves `target` out of the way and puts `replacement` in its place,
+/// moving `target` back if that fails. A running executable may be renamed
+/// even on Windows, but not replaced.
+fn swap_in(replacement: &Path, target: &Path, aside: &Path) -> io::Result<()> {
+    if aside.exists() {
+        fs::remove_file(aside)?;
+    }
+    fs::rename(target, aside)?;
+    if let Err(err) = fs::rename(replacement, target) {
+        warn!("replace {:?}: {}, restoring it", target, err);
+        let _ = fs::rename(aside, target);
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Replaces `target` with `binary`, keeping the old one at `backup_path(target)`.
+/// Both renames stay on the same volume, so `target` is never half written,
+/// and a running executable may be renamed even on Windows.
+pub fn install_update(binary: &Path, target: &Path) -> io::Result<PathBuf> {
+    let name = binary.file_name().unwrap_or_default().to_string_lossy();
+    if !is_executable_asset(&name, env::consts::OS) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{name} is not an executable"),
+        ));
+    }
+
+    let staged = sibling(target, ".new");
+    fs::copy(binary, &staged)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&staged, fs::Permissions::from_mode(0o755))?;
+    }
+
+    let backup = backup_path(target);
+    swap_in(&staged, target, &backup).inspect_err(|_| {
+        let _ = fs::remove_file(&staged);
+    })?;
+
+    info!("installed update to {:?}, backup at {:?}", target, &backup);
+    Ok(backup)
+}
+
+/// Puts the executable from before the last update back in place. The
+/// running one is moved aside first and deleted where the system allows it.
+pub fn rollback(target: &Path) -> io::Result<()> {
+    let backup = backup_path(target);
+    if !backup.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no backup at {}", backup.display()),
+        ));
+    }
+    let replaced = sibling(target, ".old");
+    swap_in(&backup, target, &replaced)?;
+    // Windows keeps a running executable, it is removed by the next rollback
+    let _ = fs::remove_file(&replaced);
+    Ok(())
+}
+
+/// Starts the (updated) executable again, the caller should exit right after.
+pub fn relaunch(target: &Path) -> io::Result<()> {
+    Command::new(target).args(env::args_os().skip(1)).spawn()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{self, Response};
+    use sha2::{Digest, Sha256};
+
+    fn v(tag: &str) -> Version {
+        Version::parse(tag).unwrap()
+    }
+
+    #[test]
+    fn orders_versions_with_prerelease_suffixes() {
+        assert!(v("v2.0.2-b8") < v("v2.0.2-b10"));
+        assert!(v("v2.0.2-b10") < v("v2.0.2"));
+        assert!(v("v2.0.2") < v("v2.0.3-b1"));
+        assert!(v("2.0.2-1") < v("2.0.2-b1"));
+        assert!(v("v2.1") > v("v2.0.9"));
+        assert_eq!(v("v2.0.2-b8").to_string(), "v2.0.2-b8");
+        assert!(Version::parse("nightly").is_none());
+    }
+
+    #[tokio::test]
+    async fn finds_updates_per_channel() {
+        let server = test_server::serve(|req| {
+            assert!(req.path.starts_with("/repos/cangzhang/champ-r/releases"));
+            assert_eq!(
+                req.header("user-agent").map(String::as_str),
+                Some("ChampR_rs")
+            );
+            Response::ok(
+                r#"[
+                {"name": "draft", "tag_name": "v9.0.0", "html_url": "", "draft": true, "prerelease": false},
+                {"name": "nightly", "tag_name": "v2.0.3-b2", "html_url": "https://example/b2", "draft": false, "prerelease": true},
+                {"name": "stable", "tag_name": "v2.0.2", "html_url": "https://example/2.0.2", "draft": false, "prerelease": false},
+                {"name": "old", "tag_name": "v2.0.1", "html_url": "", "draft": false, "prerelease": false}
+            ]"#,
+            )
+        })
+        .await;
+        let api = server.url("/repos/cangzhang/champ-r");
+        let releases = list_releases(&api).await.unwrap();
+
+        let stable = pick_update(releases.clone(), "v2.0.2-b8", UpdateChannel::Stable).unwrap();
+        assert_eq!(stable.html_url, "https://example/2.0.2");
+        let nightly = pick_update(releases.clone(), "v2.0.2-b8", UpdateChannel::Nightly).unwrap();
+        assert_eq!(nightly.tag_name, "v2.0.3-b2");
+        assert!(pick_update(releases, "v2.0.2", UpdateChannel::Stable).is_none());
+    }
+
+    fn asset(name: &str, url: String, digest: Option<String>) -> ReleaseAsset {
+        ReleaseAsset {
+            name: name.to_string(),
+            browser_download_url: url,
+            digest,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn picks_the_asset_for_the_platform() {
+        let release = LatestRelease {
+            assets: [
+                "ChampR_v2.1.0.exe",
+                "champr-linux-arm64",
+                "champr-linux-x86_64",
+                "champr-linux-x86_64.sha256",
+            ]
+            .into_iter()
+            .map(|name| asset(name, String::new(), None))
+            .collect(),
+            ..Default::default()
+        };
+        let name = |os, arch| platform_asset(&release, os, arch).map(|a| a.name.as_str());
+        assert_eq!(name("windows", "x86_64"), Some("ChampR_v2.1.0.exe"));
+        assert_eq!(name("linux", "x86_64"), Some("champr-linux-x86_64"));
+        assert_eq!(name("linux", "aarch64"), Some("champr-linux-arm64"));
+        assert_eq!(name("macos", "aarch64"), None);
+
+        let packaged = LatestRelease {
+            assets: ["champr-windows-x64.zip", "champr-macos-arm64.dmg"]
+                .into_iter()
+                .map(|name| asset(name, String::new(), None))
+                .collect(),
+            ..Default::default()
+        };
+        assert!(platform_asset(&packaged, "windows", "x86_64").is_none());
+        assert!(platform_asset(&packaged, "macos", "aarch64").is_none());
+    }
+
+    #[tokio::test]
+    async fn downloads_verifies_and_swaps_the_binary() {
+        let name = format!("champr-{}-{}", env::consts::OS, env::consts::ARCH);
+        let server = test_server::serve(|req| match req.path.as_str() {
+            "/good" => Response::ok("new build"),
+            "/bad.sha256" => Response::ok(format!("{:x}  bad", Sha256::digest(b"new build"))),
+            _ => Response::ok("tampered"),
+        })
+        .await;
+        let digest = format!("sha256:{:x}", Sha256::digest(b"new build"));
+        let release = |path: &str, digest: Option<String>| LatestRelease {
+            tag_name: String::from("v2.1.0"),
+            assets: vec![
+                asset(&name, server.url(path), digest),
+                asset(&format!("{name}.sha256"), server.url("/bad.sha256"), None),
+            ],
+            ..Default::default()
+        };
+
+        let dir = test_server::temp_dir("updater");
+        let target = dir.join("champr");
+        fs::write(&target, "old build").unwrap();
+
+        let tampered = download_update(
+            &release("/bad", None),
+            &dir.join("dl"),
+            &Progress::default(),
+        )
+        .await;
+        assert!(matches!(tampered, Err(FetchError::Integrity { .. })));
+        assert!(fs::read_dir(dir.join("dl")).unwrap().next().is_none());
+
+        let binary = download_update(
+            &release("/good", Some(digest)),
+            &dir.join("dl"),
+            &Progress::default(),
+        )
+        .await
+        .unwrap();
+        let backup = install_update(&binary, &target).unwrap();
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new build");
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "old build");
+
+        rollback(&target).unwrap();
+        assert_eq!(fs::read_to_string(&target).unwrap(), "old build");
+        assert!(rollback(&target).is_err());
+    }
+}
@@ -1,44 +1,45 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fs,
-    io::{self, Cursor},
     path::Path,
 };
 
-use anyhow::{anyhow, Context};
-use flate2::read::GzDecoder;
+use anyhow::Context;
+use bytes::Bytes;
 use futures::future::join_all;
 use futures::future::try_join3;
 use kv_log_macro::{error, info, warn};
-use reqwest::header::USER_AGENT;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use tar::Archive;
 
 use crate::{
+    archive::{self, ChannelReader},
     builds::{self, BuildData, ItemBuild},
+    cache::{BUILDS_POLICY, GAME_DATA_POLICY, SOURCES_POLICY},
+    cancel::{self, CancellationToken, Cancelled},
+    ddragon::{self, GameDataProvider},
+    endpoints::{self, Mirrors},
+    fetch_error,
+    integrity::Verifier,
+    item_sets::{ItemSetWriter, Plan, WriteReport},
+    items::{self, ItemCatalog},
+    merge::{self, MERGED_SOURCE},
+    pool::ChampionPool,
+    progress::{ApplyStage, Progress, ProgressSender},
+    provider,
     source::SourceItem,
 };
 
-pub const SERVICE_URL: &str = "https://c.lbj.moe";
-
-#[derive(Debug, Clone)]
-pub enum FetchError {
-    Failed,
-}
+pub use crate::fetch_error::FetchError;
 
 pub async fn fetch_sources() -> Result<Vec<SourceItem>, FetchError> {
-    let url = format!("{SERVICE_URL}/api/sources");
-    match reqwest::get(url).await {
-        Ok(resp) => resp.json::<Vec<SourceItem>>().await.map_err(|err| {
-            error!("source list serialize: {:?}", err);
-            FetchError::Failed
-        }),
-        Err(err) => {
-            error!("fetch source list: {:?}", err);
-            Err(FetchError::Failed)
-        }
-    }
+    endpoints::service()
+        .get_cached_json::<Vec<SourceItem>>("/api/sources", SOURCES_POLICY)
+        .await
+        .map_err(|err| {
+            error!("fetch source list: {}", err);
+            err
+        })
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -72,14 +73,17 @@ pub struct Image {
 pub type ChampionsMap = HashMap<String, ChampInfo>;
 
 pub async fn fetch_champion_list() -> Result<ChampionsMap, FetchError> {
-    let url = format!("{SERVICE_URL}/api/data-dragon/champions",);
-    if let Ok(resp) = reqwest::get(url).await {
-        if let Ok(data) = resp.json::<ChampionsMap>().await {
-            return Ok(data);
+    match ddragon::provider() {
+        GameDataProvider::Service => {
+            endpoints::service()
+                .get_cached_json("/api/data-dragon/champions", GAME_DATA_POLICY)
+                .await
+        }
+        GameDataProvider::DataDragon => {
+            let dd = ddragon::shared();
+            dd.champions(&dd.latest_version().await?).await
         }
     }
-
-    Err(FetchError::Failed)
 }
 
 pub async fn init_for_ui(
@@ -105,18 +109,15 @@ pub struct ListBuildsResp {
     pub content: Vec<builds::BuildSection>,
 }
 
-pub async fn list_builds(url: &String) -> Result<Vec<builds::BuildSection>, FetchError> {
-    match reqwest::get(url).await {
-        Ok(resp) => match resp.json::<ListBuildsResp>().await {
-            Ok(resp) => Ok(resp.content),
-            Err(e) => {
-                println!("list_builds_by_alias: {:?}", e);
-                Err(FetchError::Failed)
-            }
-        },
+pub async fn list_builds(path: &str) -> Result<Vec<builds::BuildSection>, FetchError> {
+    match endpoints::service()
+        .get_cached_json::<ListBuildsResp>(path, BUILDS_POLICY)
+        .await
+    {
+        Ok(resp) => Ok(resp.content),
         Err(err) => {
-            println!("fetch source list: {:?}", err);
-            Err(FetchError::Failed)
+            warn!("list builds {}: {}", path, err);
+            Err(err)
         }
     }
 }
@@ -125,16 +126,14 @@ pub async fn list_builds_by_alias(
     source: &String,
     champion: &String,
 ) -> Result<Vec<builds::BuildSection>, FetchError> {
-    let url = format!("{SERVICE_URL}/api/source/{source}/champion-alias/{champion}");
-    list_builds(&url).await
+    list_builds(&format!("/api/source/{source}/champion-alias/{champion}")).await
 }
 
 pub async fn list_builds_by_id(
     source: &String,
     champion_id: i64,
 ) -> Result<Vec<builds::BuildSection>, FetchError> {
-    let url = format!("{SERVICE_URL}/api/source/{source}/champion-id/{champion_id}");
-    list_builds(&url).await
+    list_builds(&format!("/api/source/{source}/champion-id/{champion_id}")).await
 }
 
 pub async fn fetch_champion_runes(
@@ -175,13 +174,17 @@ pub struct DataDragonRune {
 }
 
 pub async fn fetch_data_dragon_runes() -> Result<Vec<DataDragonRune>, FetchError> {
-    if let Ok(resp) = reqwest::get(format!("{SERVICE_URL}/api/data-dragon/runes")).await {
-        if let Ok(data) = resp.json::<Vec<DataDragonRune>>().await {
-            return Ok(data);
+    match ddragon::provider() {
+        GameDataProvider::Service => {
+            endpoints::service()
+                .get_cached_json("/api/data-dragon/runes", GAME_DATA_POLICY)
+                .await
+        }
+        GameDataProvider::DataDragon => {
+            let dd = ddragon::shared();
+            dd.runes(&dd.latest_version().await?).await
         }
     }
-
-    Err(FetchError::Failed)
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -189,26 +192,25 @@ pub struct LatestRelease {
     pub name: String,
     pub tag_name: String,
     pub html_url: String,
+    #[serde(default)]
+    pub draft: bool,
+    #[serde(default)]
+    pub prerelease: bool,
+    #[serde(default)]
+    pub body: Option<String>,
+    #[serde(default)]
+    pub assets: Vec<ReleaseAsset>,
 }
 
-pub async fn fetch_latest_release() -> Result<LatestRelease, FetchError> {
-    let client = reqwest::Client::new();
-
-    match client
-        .get("https://api.github.com/repos/cangzhang/champ-r/releases/latest".to_string())
-        .header(USER_AGENT, "ChampR_rs")
-        .send()
-        .await
-    {
-        Ok(resp) => resp.json::<LatestRelease>().await.map_err(|err| {
-            error!("latest release serialize: {:?}", err);
-            FetchError::Failed
-        }),
-        Err(err) => {
-            error!("fetch latest release: {:?}", err);
-            Err(FetchError::Failed)
-        }
-    }
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReleaseAsset {
+    pub name: String,
+    pub browser_download_url: String,
+    #[serde(default)]
+    pub size: u64,
+    /// `sha256:<hex>`, published by GitHub for newer uploads.
+    #[serde(default)]
+    pub digest: Option<String>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -217,6 +219,8 @@ pub struct Dist {
     pub tarball: String,
     pub file_count: i64,
     pub unpacked_size: i64,
+    pub integrity: Option<String>,
+    pub shasum: Option<String>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -229,28 +233,93 @@ pub struct Package {
     pub dist: Dist,
 }
 
-pub async fn get_remote_package_data(source: &String) -> Result<(String, String), reqwest::Error> {
-    let r = reqwest::get(format!(
-        "https://mirrors.cloud.tencent.com/npm/@champ-r/{source}/latest"
-    ))
-    .await?;
-    let pak = r.json::<Package>().await?;
+pub async fn fetch_package(mirrors: &Mirrors, source: &str) -> Result<Package, FetchError> {
+    mirrors
+        .failover(|registry| async move {
+            let resp = reqwest::get(format!("{registry}/@champ-r/{source}/latest")).await?;
+            fetch_error::read_json::<Package>(resp).await
+        })
+        .await
+}
+
+pub async fn get_remote_package_data(source: &str) -> Result<(String, String), FetchError> {
+    let pak = fetch_package(endpoints::npm(), source).await?;
     Ok((pak.version, pak.dist.tarball))
 }
 
-pub async fn download_and_extract_tgz(url: &str, output_dir: &str) -> io::Result<()> {
-    // Download the file
-    let response = reqwest::get(url).await.unwrap();
-    let content = response.bytes().await.unwrap();
-    // Cursor allows us to read bytes as a stream
-    let cursor = Cursor::new(content);
-    // Decompress gzip
-    let gz = GzDecoder::new(cursor);
-    // Extract tarball
-    let mut archive = Archive::new(gz);
-    archive.unpack(output_dir)?;
+/// Streams the tarball through the checksum and the gzip decoder into a staging
+/// directory, which only replaces the contents of `output_dir` once the download
+/// has been verified. Cancelling `cancel` discards the staging directory and
+/// keeps the previous contents.
+pub async fn download_and_extract_tgz(
+    dist: &Dist,
+    output_dir: &str,
+    progress: &Progress,
+    cancel: &CancellationToken,
+) -> anyhow::Result<()> {
+    let mut verifier = Verifier::for_dist(dist)?;
+    let max_size = if dist.unpacked_size > 0 {
+        dist.unpacked_size as u64
+    } else {
+        archive::DEFAULT_MAX_UNPACKED_SIZE
+    };
+
+    let output_dir = Path::new(output_dir);
+    let staging = output_dir.join(format!(".staging-{}", rand::random::<u32>()));
+    let (tx, rx) = tokio::sync::mpsc::channel::<Bytes>(16);
+    let staging_task = staging.clone();
+    let extract = tokio::task::spawn_blocking(move || {
+        archive::unpack(ChannelReader::new(rx), &staging_task, max_size)
+    });
+
+    let download = async {
+        let mut resp = tokio::select! {
+            resp = reqwest::get(&dist.tarball) => resp?,
+            _ = cancel.cancelled() => return Err(Cancelled.into()),
+        };
+        if !resp.status().is_success() {
+            return Err(FetchError::status(&dist.tarball, resp.status().as_u16(), "").into());
+        }
+        let total = resp.content_length();
+        let mut received = 0u64;
+        progress.emit(ApplyStage::Downloading { received, total });
+        loop {
+            let chunk = tokio::select! {
+                chunk = resp.chunk() => chunk.map_err(FetchError::from)?,
+                _ = cancel.cancelled() => return Err(Cancelled.into()),
+            };
+            let Some(chunk) = chunk else {
+                break;
+            };
+            verifier.update(&chunk);
+            received += chunk.len() as u64;
+            progress.emit(ApplyStage::Downloading { received, total });
+            if tx.send(chunk).await.is_err() {
+                // the extractor gave up, its error is reported below
+                break;
+            }
+        }
+        anyhow::Ok(())
+    };
+    let downloaded = download.await;
+    drop(tx);
+    progress.emit(ApplyStage::Extracting);
+    let extracted = extract.await;
+
+    let result = (|| -> anyhow::Result<()> {
+        downloaded?;
+        let size = extracted??;
+        cancel::check(cancel)?;
+        verifier.finish(&dist.tarball)?;
+        info!("extracted {} bytes from {}", size, &dist.tarball);
+        archive::promote(&staging, output_dir)?;
+        Ok(())
+    })();
 
-    Ok(())
+    if result.is_err() && staging.exists() {
+        let _ = fs::remove_dir_all(&staging);
+    }
+    result
 }
 
 pub async fn read_local_build_file(file_path: String) -> anyhow::Result<Value> {
@@ -308,65 +377,563 @@ pub async fn read_from_local_folder(
     Ok(files)
 }
 
+/// Installs the package of `source` and writes its builds with `writer`,
+/// limited to the champions and positions in `pool`; the sets of champions
+/// outside it are left as they are. When `progress` is given, every stage is
+/// reported on it, ending with either `Done` or `Failed`. Once
+/// `cancel` fires, no further file is written; the ones already written are
+/// complete.
 pub async fn download_tar_and_apply_for_source(
-    source: &String,
-    lol_dir: Option<String>,
-    is_tencent: bool,
+    source: &str,
+    writer: Option<&ItemSetWriter>,
+    pool: &ChampionPool,
+    progress: Option<ProgressSender>,
+    cancel: CancellationToken,
 ) -> anyhow::Result<()> {
-    let (_version, tar_url) = get_remote_package_data(source).await?;
+    let progress = Progress::new(source, progress);
+    let result = async {
+        match writer {
+            Some(writer) => {
+                let plan = plan_source_package(source, writer, pool, &progress, &cancel).await?;
+                if !plan.is_empty() {
+                    writer.back_up().context("back up item sets")?;
+                }
+                write_plan(writer, &plan, &progress, &cancel)?;
+            }
+            None => {
+                fetch_source_bundle(source, &progress, &cancel).await?;
+            }
+        }
+        anyhow::Ok(())
+    }
+    .await;
+    finish(&progress, result)
+}
+
+/// Installs the package of `source` and works out how `writer` would change
+/// the item sets, without writing anything.
+pub async fn plan_for_source(
+    source: &str,
+    writer: &ItemSetWriter,
+    pool: &ChampionPool,
+    progress: Option<ProgressSender>,
+    cancel: CancellationToken,
+) -> anyhow::Result<Plan> {
+    let progress = Progress::new(source, progress);
+    let result = plan_source_package(source, writer, pool, &progress, &cancel).await;
+    finish(&progress, result)
+}
+
+/// Like `plan_for_source`, but merges the builds of `sources`, ordered by
+/// priority, into one item set per champion and position. The item sets written
+/// for each source on its own are deleted, as are all of them for champions
+/// in `pool` none of the sources has builds for anymore.
+pub async fn plan_merged(
+    sources: &[String],
+    writer: &ItemSetWriter,
+    pool: &ChampionPool,
+    progress: Option<ProgressSender>,
+    cancel: CancellationToken,
+) -> anyhow::Result<Plan> {
+    let tasks = sources.iter().map(|source| {
+        let progress = Progress::new(source, progress.clone());
+        let cancel = cancel.clone();
+        async move {
+            let result = fetch_source_bundle(source, &progress, &cancel).await;
+            if result.is_ok() {
+                progress.emit(ApplyStage::Planning);
+            }
+            (progress, result)
+        }
+    });
+    let mut bundles: Vec<(String, provider::Bundle)> = vec![];
+    let mut failed = None;
+    for (source, (progress, result)) in sources.iter().zip(join_all(tasks).await) {
+        match finish(&progress, result) {
+            Ok(bundle) => bundles.push((source.clone(), bundle)),
+            Err(err) => failed = failed.or(Some(err)),
+        }
+    }
+    if let Some(err) = failed {
+        return Err(err);
+    }
+
+    let catalog = items::get().await;
+    let plan = plan_merged_bundles(sources, bundles, writer, pool, &catalog, &cancel)?;
+    info!(
+        "planned merged item sets of {:?}: {}",
+        sources,
+        plan.summary()
+    );
 
-    info!("found download url for {}, {}", &source, &tar_url);
+    Ok(plan)
+}
 
-    let output_dir = format!(".npm/{source}");
-    let output_path = Path::new(&output_dir);
+fn plan_merged_bundles(
+    sources: &[String],
+    bundles: Vec<(String, provider::Bundle)>,
+    writer: &ItemSetWriter,
+    pool: &ChampionPool,
+    catalog: &ItemCatalog,
+    cancel: &CancellationToken,
+) -> anyhow::Result<Plan> {
+    let filter = writer.filter();
+    let substitutions = items::substitutions();
+    let mut item_changes = vec![];
+    let mut by_champion: BTreeMap<String, Vec<merge::SourceSections>> = BTreeMap::new();
+    for (source, bundle) in bundles {
+        for mut sections in bundle {
+            let Some(alias) = sections.first().map(|s| s.alias.clone()) else {
+                continue;
+            };
+            if !pool.has_champion(&alias) {
+                continue;
+            }
+            pool.filter_sections(&mut sections);
+            filter.filter_sections(&mut sections);
+            item_changes.extend(catalog.check_sections(&source, &mut sections, &substitutions));
+            by_champion
+                .entry(alias)
+                .or_default()
+                .push((source.clone(), sections));
+        }
+    }
 
-    if let Err(err) = fs::create_dir_all(output_path) {
-        error!("create output dir: {:?}", err);
-        return Err(anyhow!("create output dir: {:?}", err));
+    let mut written = writer.champions_of(MERGED_SOURCE)?;
+    for source in sources {
+        written.extend(writer.champions_of(source)?);
+    }
+    let dropped = written
+        .into_iter()
+        .filter(|alias| !by_champion.contains_key(alias) && pool.has_champion(alias))
+        .map(|alias| (alias, vec![]))
+        .collect::<Vec<_>>();
+
+    let mut plan = Plan {
+        item_changes,
+        ..Default::default()
+    };
+    for (alias, sections) in by_champion.into_iter().chain(dropped) {
+        cancel::check(cancel)?;
+        let merged = merge::merge_sections(&sections);
+        let champion_plan = writer
+            .plan(MERGED_SOURCE, &alias, &merged)
+            .with_context(|| format!("read item sets of {alias}"))?;
+        plan.extend(champion_plan);
+        for source in sources {
+            plan.extend(writer.plan(source, &alias, &[])?);
+        }
     }
 
-    download_and_extract_tgz(&tar_url, &output_dir).await?;
-    let dest_folder = format!("{}/package", &output_dir);
-    let files = read_from_local_folder(&dest_folder).await?;
+    Ok(plan)
+}
+
+/// Carries out a plan made by `plan_for_source` or `plan_merged`. The caller
+/// takes the snapshot of the run first, see `ItemSetWriter::back_up`.
+pub fn apply_plan(
+    source: &str,
+    lol_dir: &str,
+    is_tencent: bool,
+    plan: &Plan,
+    progress: Option<ProgressSender>,
+    cancel: CancellationToken,
+) -> anyhow::Result<WriteReport> {
+    let progress = Progress::new(source, progress);
+    let writer = ItemSetWriter::for_game(lol_dir, is_tencent);
+    let result = write_plan(&writer, plan, &progress, &cancel);
+    finish(&progress, result)
+}
+
+fn finish<T>(progress: &Progress, result: anyhow::Result<T>) -> anyhow::Result<T> {
+    match &result {
+        Ok(_) => progress.emit(ApplyStage::Done),
+        Err(err) => progress.emit(ApplyStage::Failed(format!("{err:#}"))),
+    }
+    result
+}
 
-    info!("found {} builds for {}", files.len(), source);
+async fn fetch_source_bundle(
+    source: &str,
+    progress: &Progress,
+    cancel: &CancellationToken,
+) -> anyhow::Result<provider::Bundle> {
+    let provider = provider::for_source(source)?;
+    let files = provider.fetch_bundle(source, progress, cancel).await?;
+
+    info!(
+        "found {} builds for {} from {}",
+        files.len(),
+        source,
+        provider.id()
+    );
+    Ok(files)
+}
 
-    if lol_dir.is_some() {
-        let dir = lol_dir.unwrap();
+async fn plan_source_package(
+    source: &str,
+    writer: &ItemSetWriter,
+    pool: &ChampionPool,
+    progress: &Progress,
+    cancel: &CancellationToken,
+) -> anyhow::Result<Plan> {
+    let files = fetch_source_bundle(source, progress, cancel).await?;
+
+    progress.emit(ApplyStage::Planning);
+    let catalog = items::get().await;
+    let plan = plan_bundle(source, files, writer, pool, &catalog, cancel)?;
+    info!("planned item sets of {}: {}", source, plan.summary());
+
+    Ok(plan)
+}
 
-        files.iter().for_each(|sections| {
-            let sections = sections.clone();
-            let alias = sections[0].alias.clone();
-            builds::apply_builds_from_data(sections, &dir.clone(), source, &alias, is_tencent);
-        });
+/// The plan for the builds of `source` in `bundle`. Champions in `pool` it has
+/// no builds for lose the item sets written for them earlier.
+fn plan_bundle(
+    source: &str,
+    bundle: provider::Bundle,
+    writer: &ItemSetWriter,
+    pool: &ChampionPool,
+    catalog: &ItemCatalog,
+    cancel: &CancellationToken,
+) -> anyhow::Result<Plan> {
+    let filter = writer.filter();
+    let substitutions = items::substitutions();
+    let mut dropped = writer.champions_of(source)?;
+    let mut plan = Plan::default();
+    for mut sections in bundle {
+        cancel::check(cancel)?;
+        let Some(alias) = sections.first().map(|s| s.alias.clone()) else {
+            continue;
+        };
+        dropped.remove(&alias);
+        if !pool.has_champion(&alias) {
+            // sets written earlier for champions outside the pool are kept
+            continue;
+        }
+        pool.filter_sections(&mut sections);
+        filter.filter_sections(&mut sections);
+        let item_changes = catalog.check_sections(source, &mut sections, &substitutions);
+        let champion_plan = writer
+            .plan(source, &alias, &sections)
+            .with_context(|| format!("read item sets of {alias}"))?;
+        plan.extend(champion_plan);
+        plan.item_changes.extend(item_changes);
+    }
+    for alias in dropped.iter().filter(|alias| pool.has_champion(alias)) {
+        cancel::check(cancel)?;
+        plan.extend(writer.plan(source, alias, &[])?);
     }
 
-    Ok(())
+    Ok(plan)
+}
+
+fn write_plan(
+    writer: &ItemSetWriter,
+    plan: &Plan,
+    progress: &Progress,
+    cancel: &CancellationToken,
+) -> anyhow::Result<WriteReport> {
+    let report = writer.execute(plan, progress, cancel);
+    cancel::check(cancel)?;
+    Ok(report.into_result()?)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        item_sets::ChangeKind,
+        test_server::{self, Response},
+    };
 
     #[tokio::test]
+    #[ignore = "downloads the package from the npm registry"]
     async fn apply_builds_for_riot_server() -> anyhow::Result<()> {
-        femme::with_level(femme::LevelFilter::Info);
-
         let source = String::from("op.gg");
-        download_tar_and_apply_for_source(&source, Some(String::from(".local_builds")), false)
-            .await?;
+        download_tar_and_apply_for_source(
+            &source,
+            Some(&ItemSetWriter::for_game(".local_builds", false)),
+            &ChampionPool::all(),
+            None,
+            CancellationToken::new(),
+        )
+        .await?;
 
         Ok(())
     }
 
     #[tokio::test]
+    #[ignore = "downloads the package from the npm registry"]
     async fn apply_builds_for_tencent_server() -> anyhow::Result<()> {
-        femme::with_level(femme::LevelFilter::Info);
-
         let source = String::from("op.gg");
-        download_tar_and_apply_for_source(&source, Some(String::from(".local_builds")), true)
-            .await?;
+        download_tar_and_apply_for_source(
+            &source,
+            Some(&ItemSetWriter::for_game(".local_builds", true)),
+            &ChampionPool::all(),
+            None,
+            CancellationToken::new(),
+        )
+        .await?;
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn keeps_previous_package_when_download_is_tampered() {
+        let good = test_server::make_tgz(&[("package/Ahri.json", b"[]")]);
+        let evil = test_server::make_tgz(&[("package/Ahri.json", b"{}")]);
+        let server = test_server::serve(move |_| Response::ok(evil.clone())).await;
+        let pkg: Package = serde_json::from_str(&test_server::package_json(
+            "1.0.0",
+            &server.url("/op.gg.tgz"),
+            &good,
+            2,
+        ))
+        .unwrap();
+        let output_dir = test_server::temp_dir("npm");
+        fs::create_dir_all(output_dir.join("package")).unwrap();
+        fs::write(output_dir.join("package/Ahri.json"), "old").unwrap();
+
+        let err = download_and_extract_tgz(
+            &pkg.dist,
+            &output_dir.to_string_lossy(),
+            &Progress::default(),
+            &CancellationToken::new(),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<FetchError>(),
+            Some(FetchError::Integrity { .. })
+        ));
+        assert_eq!(
+            fs::read_to_string(output_dir.join("package/Ahri.json")).unwrap(),
+            "old"
+        );
+        assert_eq!(fs::read_dir(&output_dir).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn verifies_tail_arriving_after_end_of_archive() {
+        // npm pads tarballs to full records; stored uncompressed, the padding
+        // after the end-of-archive marker spans many chunks
+        let mut builder = tar::Builder::new(vec![]);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(2);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "package/Ahri.json", &b"[]"[..])
+            .unwrap();
+        let mut tar = builder.into_inner().unwrap();
+        tar.resize(tar.len() + 64 * 1024, 0);
+        let mut gz = flate2::write::GzEncoder::new(vec![], flate2::Compression::none());
+        std::io::Write::write_all(&mut gz, &tar).unwrap();
+        let tgz = gz.finish().unwrap();
+
+        let body = tgz.clone();
+        let server = test_server::serve(move |_| Response::ok(body.clone()).in_chunks(1024)).await;
+        let pkg: Package = serde_json::from_str(&test_server::package_json(
+            "1.0.0",
+            &server.url("/op.gg.tgz"),
+            &tgz,
+            2,
+        ))
+        .unwrap();
+        let output_dir = test_server::temp_dir("npm");
+
+        download_and_extract_tgz(
+            &pkg.dist,
+            &output_dir.to_string_lossy(),
+            &Progress::default(),
+            &CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(output_dir.join("package/Ahri.json")).unwrap(),
+            "[]"
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_download_progress() {
+        let tgz = test_server::make_tgz(&[("package/Ahri.json", b"[]")]);
+        let body = tgz.clone();
+        let server = test_server::serve(move |_| Response::ok(body.clone())).await;
+        let pkg: Package = serde_json::from_str(&test_server::package_json(
+            "1.0.0",
+            &server.url("/op.gg.tgz"),
+            &tgz,
+            2,
+        ))
+        .unwrap();
+        let output_dir = test_server::temp_dir("npm");
+        let (tx, mut rx) = crate::progress::channel();
+
+        download_and_extract_tgz(
+            &pkg.dist,
+            &output_dir.to_string_lossy(),
+            &Progress::new("op.gg", Some(tx)),
+            &CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+
+        let mut stages = vec![];
+        while let Ok(event) = rx.try_recv() {
+            assert_eq!(event.source, "op.gg");
+            stages.push(event.stage);
+        }
+        assert_eq!(
+            stages.iter().rev().nth(1),
+            Some(&ApplyStage::Downloading {
+                received: tgz.len() as u64,
+                total: Some(tgz.len() as u64),
+            })
+        );
+        assert_eq!(stages.last(), Some(&ApplyStage::Extracting));
+    }
+
+    #[tokio::test]
+    async fn cancelled_download_keeps_previous_package() {
+        let tgz = test_server::make_tgz(&[("package/Ahri.json", b"[]")]);
+        let body = tgz.clone();
+        let server = test_server::serve(move |_| Response::ok(body.clone())).await;
+        let pkg: Package = serde_json::from_str(&test_server::package_json(
+            "1.0.0",
+            &server.url("/op.gg.tgz"),
+            &tgz,
+            2,
+        ))
+        .unwrap();
+        let output_dir = test_server::temp_dir("npm");
+        fs::create_dir_all(output_dir.join("package")).unwrap();
+        fs::write(output_dir.join("package/Ahri.json"), "old").unwrap();
+
+        let token = CancellationToken::new();
+        token.cancel();
+        let err = download_and_extract_tgz(
+            &pkg.dist,
+            &output_dir.to_string_lossy(),
+            &Progress::default(),
+            &token,
+        )
+        .await
+        .unwrap_err();
+        assert!(cancel::is_cancelled(&err));
+        assert_eq!(
+            fs::read_to_string(output_dir.join("package/Ahri.json")).unwrap(),
+            "old"
+        );
+        assert_eq!(fs::read_dir(&output_dir).unwrap().count(), 1);
+    }
+
+    fn champion(alias: &str) -> Vec<builds::BuildSection> {
+        vec![builds::BuildSection {
+            alias: alias.to_string(),
+            position: String::from("mid"),
+            item_builds: vec![ItemBuild {
+                title: format!("{alias} mid"),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }]
+    }
+
+    fn writer_in(name: &str) -> ItemSetWriter {
+        let lol_dir = test_server::temp_dir(name);
+        ItemSetWriter::for_game(&lol_dir.to_string_lossy(), false)
+            .with_manifest(lol_dir.join("manifest.json"))
+            .with_history(lol_dir.join("history.json"))
+            .with_backups(lol_dir.join("backups"))
+    }
+
+    fn deleted(plan: &Plan) -> Vec<(&str, &str)> {
+        plan.changes
+            .iter()
+            .filter(|c| c.kind == ChangeKind::Delete)
+            .map(|c| (c.source.as_str(), c.champion.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn deletes_sets_of_champions_the_bundle_dropped() {
+        let writer = writer_in("dropped-champions");
+        let catalog = ItemCatalog::default();
+        let cancel = CancellationToken::new();
+        let bundle = vec![champion("Ahri"), champion("Annie"), champion("Zed")];
+        let plan = plan_bundle(
+            "op.gg",
+            bundle,
+            &writer,
+            &ChampionPool::all(),
+            &catalog,
+            &cancel,
+        )
+        .unwrap();
+        writer.execute(&plan, &Progress::default(), &cancel);
+
+        // Annie is outside the pool and keeps her sets
+        let pool = ChampionPool::new(Some(&["Ahri", "Zed"]), &[]);
+        let plan = plan_bundle(
+            "op.gg",
+            vec![champion("Ahri")],
+            &writer,
+            &pool,
+            &catalog,
+            &cancel,
+        )
+        .unwrap();
+        assert_eq!(deleted(&plan), [("op.gg", "Zed")]);
+        assert_eq!(plan.unchanged, 1);
+    }
+
+    #[test]
+    fn deletes_merged_sets_of_champions_no_source_has() {
+        let writer = writer_in("dropped-merged-champions");
+        let catalog = ItemCatalog::default();
+        let cancel = CancellationToken::new();
+        let sources = [String::from("op.gg"), String::from("u.gg")];
+        let plan = plan_bundle(
+            "u.gg",
+            vec![champion("Annie")],
+            &writer,
+            &ChampionPool::all(),
+            &catalog,
+            &cancel,
+        )
+        .unwrap();
+        writer.execute(&plan, &Progress::default(), &cancel);
+        let bundles = vec![
+            (sources[0].clone(), vec![champion("Ahri"), champion("Zed")]),
+            (sources[1].clone(), vec![champion("Ahri")]),
+        ];
+        let plan = plan_merged_bundles(
+            &sources,
+            bundles,
+            &writer,
+            &ChampionPool::all(),
+            &catalog,
+            &cancel,
+        )
+        .unwrap();
+        assert_eq!(deleted(&plan), [("u.gg", "Annie")]);
+        writer.execute(&plan, &Progress::default(), &cancel);
+
+        let bundles = vec![
+            (sources[0].clone(), vec![champion("Ahri")]),
+            (sources[1].clone(), vec![champion("Ahri")]),
+        ];
+        let plan = plan_merged_bundles(
+            &sources,
+            bundles,
+            &writer,
+            &ChampionPool::all(),
+            &catalog,
+            &cancel,
+        )
+        .unwrap();
+        assert_eq!(deleted(&plan), [(MERGED_SOURCE, "Zed")]);
+    }
 }